@@ -0,0 +1,102 @@
+//! dudect-style timing test for the constant-time backend.
+//!
+//! Blocks from two classes, a fixed block and random blocks, are compressed in
+//! random order and timed. Welch's t-test then compares the two timing
+//! distributions; a |t| above 4.5 means the time taken depends on the input.
+//!
+//! Run with `cargo bench --bench ct --features ct`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use tiger_hash::TigerState;
+
+#[path = "../src/test_util/xorshift.rs"]
+mod xorshift;
+
+use xorshift::next;
+
+const MEASUREMENTS: usize = 200_000;
+const THRESHOLD: f64 = 4.5;
+
+/// Running mean and variance (Welford's algorithm).
+#[derive(Default)]
+struct Stats {
+    n: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Stats {
+    fn push(&mut self, x: f64) {
+        self.n += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.n;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        self.m2 / (self.n - 1.0)
+    }
+}
+
+/// Welch's t statistic of the measurements of both classes that are at most
+/// `cutoff`.
+fn welch_t(samples: &[(usize, f64)], cutoff: f64) -> f64 {
+    let mut stats = [Stats::default(), Stats::default()];
+    for &(class, time) in samples.iter().filter(|&&(_, time)| time <= cutoff) {
+        stats[class].push(time);
+    }
+    let [fixed, random] = stats;
+    (fixed.mean - random.mean) / (fixed.variance() / fixed.n + random.variance() / random.n).sqrt()
+}
+
+fn main() {
+    let mut seed = 0x9E3779B97F4A7C15;
+    let inputs: Vec<(usize, [u8; 64])> = (0..MEASUREMENTS)
+        .map(|_| {
+            let class = (next(&mut seed) & 1) as usize;
+            let block = if class == 0 {
+                [0; 64]
+            } else {
+                std::array::from_fn(|_| next(&mut seed) as u8)
+            };
+            (class, block)
+        })
+        .collect();
+
+    let samples: Vec<(usize, f64)> = inputs
+        .iter()
+        .map(|(class, block)| {
+            let mut state = TigerState::new();
+            let start = Instant::now();
+            state.compress_block(black_box(block));
+            black_box(state);
+            (*class, start.elapsed().as_nanos() as f64)
+        })
+        .collect();
+
+    // Like dudect, also test with the slowest measurements cropped, since
+    // interrupts and other noise only ever make measurements longer.
+    let mut sorted: Vec<f64> = samples.iter().map(|&(_, time)| time).collect();
+    sorted.sort_by(f64::total_cmp);
+    let mut max_t: f64 = 0.0;
+    for percentile in [100, 99, 95, 90, 75, 50] {
+        let cutoff = sorted[(sorted.len() - 1) * percentile / 100];
+        let t = welch_t(&samples, cutoff);
+        println!(
+            "below p{:<3} ({:>8.0} ns): t = {:>7.2}",
+            percentile, cutoff, t
+        );
+        max_t = max_t.max(t.abs());
+    }
+
+    if max_t > THRESHOLD {
+        println!(
+            "max |t| = {:.2} > {}: timing depends on the input",
+            max_t, THRESHOLD
+        );
+        std::process::exit(1);
+    }
+    println!("max |t| = {:.2}: no timing difference detected", max_t);
+}
@@ -0,0 +1,45 @@
+//! Throughput of `hash_many` on small records compared with hashing them one
+//! at a time.
+//!
+//! Run with `cargo bench --bench many`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Runs `f` until at least a second has passed and returns the number of
+/// records hashed per second.
+fn records_per_second(records: usize, mut f: impl FnMut()) -> f64 {
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        f();
+        iterations += 1;
+    }
+    (records * iterations) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    for record_len in [16, 64, 256, 1024] {
+        let records: Vec<Vec<u8>> = (0..10_000)
+            .map(|i| (0..record_len).map(|j| (i * 31 + j) as u8).collect())
+            .collect();
+        let refs: Vec<&[u8]> = records.iter().map(Vec::as_slice).collect();
+
+        let serial = records_per_second(refs.len(), || {
+            for record in &refs {
+                black_box(tiger_hash::digest(black_box(record)));
+            }
+        });
+        let many = records_per_second(refs.len(), || {
+            black_box(tiger_hash::hash_many(black_box(&refs)));
+        });
+
+        println!(
+            "{:5} bytes: serial {:10.0}/s, hash_many {:10.0}/s, speedup {:.2}x",
+            record_len,
+            serial,
+            many,
+            many / serial
+        );
+    }
+}
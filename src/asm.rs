@@ -0,0 +1,178 @@
+//! Hand-written x86_64 assembly for the Tiger rounds.
+//!
+//! Only baseline x86_64 instructions are used, so this backend runs on every
+//! x86_64 CPU, but it is not faster on every one: on many recent cores the
+//! compiled portable rounds win. Enabling the `asm` feature therefore only
+//! makes the backend available; [`is_faster`] times both on first use and the
+//! assembly rounds are used only if they beat the portable ones.
+
+use std::arch::asm;
+use std::hint::black_box;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::utils::{compress_with_sbox, key_schedule, passes, SboxTables, TIGER_PASSES};
+use crate::TABLES;
+
+/// Number of blocks each backend compresses per timing run of the probe.
+const PROBE_BLOCKS: usize = 1024;
+
+/// Whether the assembly rounds are faster than the portable ones on this CPU.
+///
+/// The answer is measured once, on the first call, and cached for the rest of
+/// the process. Under Miri, which can't run inline assembly, it is always
+/// `false`.
+pub(crate) fn is_faster() -> bool {
+    static FASTER: OnceLock<bool> = OnceLock::new();
+    *FASTER.get_or_init(|| !cfg!(miri) && probe())
+}
+
+/// Times both backends on the same blocks, alternating between them three
+/// times and keeping the best run of each so that a single interruption
+/// doesn't decide the outcome.
+fn probe() -> bool {
+    fn time(f: fn(&[u64; 8], &mut [u64; 3], &SboxTables)) -> Duration {
+        let mut state = [0x0123456789ABCDEF, 0xFEDCBA9876543210, 0xF096A5B4C3B2E187];
+        let mut block = [0x5A5A5A5A5A5A5A5A; 8];
+        let start = Instant::now();
+        for i in 0..PROBE_BLOCKS {
+            block[i % 8] ^= state[0];
+            f(black_box(&block), &mut state, &TABLES.wide);
+        }
+        black_box(state);
+        start.elapsed()
+    }
+
+    let (mut asm, mut portable) = (Duration::MAX, Duration::MAX);
+    for _ in 0..3 {
+        asm = asm.min(time(compress::<TIGER_PASSES>));
+        portable = portable.min(time(compress_with_sbox::<TIGER_PASSES>));
+    }
+    asm < portable
+}
+
+pub(crate) fn compress<const PASSES: usize>(
+    block: &[u64; 8],
+    state: &mut [u64; 3],
+    tables: &SboxTables,
+) {
+    let [mut a, mut b, mut c] = *state;
+    let mut x = *block;
+    passes!(PASSES, [a, b, c], x, pass, key_schedule, tables);
+
+    *state = [
+        a ^ state[0],
+        b.wrapping_sub(state[1]),
+        c.wrapping_add(state[2]),
+    ];
+}
+
+/// One round: `c ^= x`, then `a` and `b` are updated from the even and odd
+/// bytes of `c`. Table `tN` starts at byte offset `(N - 1) * 2048`.
+///
+/// Two bytes are extracted at a time through the low and high byte registers
+/// of `tmp`, which is why `tmp`, `i` and `j` must be one of `rax`-`rdx`.
+macro_rules! round {
+    ($a:literal, $b:literal, $c:literal, $x:literal) => {
+        concat!(
+            "xor ", $c, ", qword ptr [{x} + ", $x, "]\n",
+            "mov {tmp}, ", $c, "\n",
+            // bytes 0 and 1 -> t1 and t4
+            "movzx {i:e}, {tmp:l}\n",
+            "movzx {j:e}, {tmp:h}\n",
+            "mov {even}, qword ptr [{t} + {i}*8]\n",
+            "mov {odd}, qword ptr [{t} + {j}*8 + 6144]\n",
+            // bytes 2 and 3 -> t2 and t3
+            "shr {tmp}, 16\n",
+            "movzx {i:e}, {tmp:l}\n",
+            "movzx {j:e}, {tmp:h}\n",
+            "xor {even}, qword ptr [{t} + {i}*8 + 2048]\n",
+            "xor {odd}, qword ptr [{t} + {j}*8 + 4096]\n",
+            // bytes 4 and 5 -> t3 and t2
+            "shr {tmp}, 16\n",
+            "movzx {i:e}, {tmp:l}\n",
+            "movzx {j:e}, {tmp:h}\n",
+            "xor {even}, qword ptr [{t} + {i}*8 + 4096]\n",
+            "xor {odd}, qword ptr [{t} + {j}*8 + 2048]\n",
+            // bytes 6 and 7 -> t4 and t1
+            "shr {tmp}, 16\n",
+            "movzx {i:e}, {tmp:l}\n",
+            "movzx {j:e}, {tmp:h}\n",
+            "xor {even}, qword ptr [{t} + {i}*8 + 6144]\n",
+            "xor {odd}, qword ptr [{t} + {j}*8]\n",
+            "sub ", $a, ", {even}\n",
+            "add ", $b, ", {odd}\n",
+            "imul ", $b, ", {mul}\n",
+        )
+    };
+}
+
+/// Eight rounds over the words of `x`, rotating the roles of `a`, `b` and `c`.
+#[inline(always)]
+fn pass(a: &mut u64, b: &mut u64, c: &mut u64, x: &[u64; 8], mul: u64, tables: &SboxTables) {
+    // SAFETY: `x` points to 8 words and `t` to four 256-entry tables. Every
+    // table index is a single byte, so all loads stay in bounds. The block
+    // only reads memory and touches no stack.
+    unsafe {
+        asm!(
+            round!("{a}", "{b}", "{c}", "0"),
+            round!("{b}", "{c}", "{a}", "8"),
+            round!("{c}", "{a}", "{b}", "16"),
+            round!("{a}", "{b}", "{c}", "24"),
+            round!("{b}", "{c}", "{a}", "32"),
+            round!("{c}", "{a}", "{b}", "40"),
+            round!("{a}", "{b}", "{c}", "48"),
+            round!("{b}", "{c}", "{a}", "56"),
+            a = inout(reg) *a,
+            b = inout(reg) *b,
+            c = inout(reg) *c,
+            x = in(reg) x.as_ptr(),
+            t = in(reg) tables.0.as_ptr(),
+            mul = in(reg) mul,
+            tmp = out(reg_abcd) _,
+            i = out(reg_abcd) _,
+            j = out(reg_abcd) _,
+            even = out(reg) _,
+            odd = out(reg) _,
+            options(pure, readonly, nostack),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::check_compress;
+    use crate::utils::{generate_sbox_tables, TIGER_PASSES};
+    use crate::TABLES;
+
+    fn check<const PASSES: usize>(tables: &SboxTables, seed: &mut u64) {
+        check_compress::<PASSES>(tables, seed, 2000, |block, state| {
+            compress::<PASSES>(block, state, tables)
+        });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't run inline assembly")]
+    fn test_matches_portable() {
+        let mut seed = 0x9E3779B97F4A7C15;
+        check::<TIGER_PASSES>(&TABLES.wide, &mut seed);
+        check::<4>(&TABLES.wide, &mut seed);
+        check::<5>(&TABLES.wide, &mut seed);
+    }
+
+    #[test]
+    fn test_is_faster_is_cached() {
+        assert_eq!(is_faster(), is_faster());
+        #[cfg(miri)]
+        assert!(!is_faster());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't run inline assembly")]
+    fn test_custom_tables() {
+        let mut seed = 0xD1B54A32D192ED03;
+        let tables = generate_sbox_tables(&[0x42; 64], 2);
+        check::<TIGER_PASSES>(&tables, &mut seed);
+    }
+}
@@ -0,0 +1,147 @@
+//! The `--cache` and `--cache-xattr` options.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use tiger_hash::{CacheFile, DigestCache, DigestKind, FileKey, TigerDigest};
+
+/// Where digests are cached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheSpec {
+    /// In the given cache file.
+    File(String),
+    /// In extended attributes of the files themselves.
+    Xattr,
+}
+
+/// A digest cache shared by the worker threads of a tool.
+pub struct Cache {
+    program: &'static str,
+    backend: Mutex<Box<dyn DigestCache + Send>>,
+    warned: AtomicBool,
+}
+
+impl Cache {
+    /// Opens the cache described by `spec`.
+    pub fn open(spec: &CacheSpec, program: &'static str) -> io::Result<Self> {
+        let backend: Box<dyn DigestCache + Send> = match spec {
+            CacheSpec::File(path) => Box::new(
+                CacheFile::open(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?,
+            ),
+            #[cfg(target_os = "linux")]
+            CacheSpec::Xattr => {
+                eprintln!(
+                    "{}: warning: --cache-xattr can't check status change times and \
+                     misses files rewritten with their old size and modification time; \
+                     use --cache for audits",
+                    program
+                );
+                Box::new(tiger_hash::XattrCache::new())
+            }
+            #[cfg(not(target_os = "linux"))]
+            CacheSpec::Xattr => {
+                let message = "extended attribute caches are only supported on Linux";
+                return Err(io::Error::new(io::ErrorKind::Unsupported, message));
+            }
+        };
+        Ok(Self {
+            program,
+            backend: Mutex::new(backend),
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the size and the `kind` digest of the file at `path`, from the
+    /// cache if it has them for the file as it is now, otherwise by reading
+    /// it. Computed digests are recorded unless the file changed while it
+    /// was read.
+    pub fn digest(&self, path: &Path, kind: DigestKind) -> io::Result<(u64, TigerDigest)> {
+        let file = File::open(path)?;
+        let key = FileKey::from_metadata(&file.metadata()?);
+        let recorded = key.and_then(|key| self.backend.lock().unwrap().get(path, &key));
+        if let (Some(key), Some(digest)) = (key, recorded.and_then(|digests| digests.get(kind))) {
+            return Ok((key.size, digest));
+        }
+
+        let (size, digest) = kind.digest_file(&file)?;
+        let after = FileKey::from_metadata(&file.metadata()?);
+        if let Some(key) = key.filter(|key| key.size == size && Some(*key) == after) {
+            let mut digests = recorded.unwrap_or_default();
+            digests.set(kind, digest);
+            if let Err(e) = self.backend.lock().unwrap().put(path, &key, digests) {
+                self.warn(path, e);
+            }
+        }
+        Ok((size, digest))
+    }
+
+    /// Writes out the cache. Failing to is only warned about.
+    pub fn flush(&self) {
+        if let Err(e) = self.backend.lock().unwrap().flush() {
+            eprintln!(
+                "{}: warning: can't save the digest cache: {}",
+                self.program, e
+            );
+        }
+    }
+
+    /// Warns the first time the cache can't be updated.
+    fn warn(&self, path: &Path, e: io::Error) {
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "{}: warning: can't update the digest cache for {}: {}",
+                self.program,
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::common::temp_dir::temp_dir;
+    use std::fs;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_cache() {
+        let dir = temp_dir("cache");
+        let (file, cache_file) = (dir.join("file"), dir.join("cache"));
+        fs::write(&file, b"abc").unwrap();
+        let spec = CacheSpec::File(cache_file.display().to_string());
+
+        let cache = Cache::open(&spec, "test").unwrap();
+        let tiger = (3, tiger_hash::digest(b"abc"));
+        assert_eq!(cache.digest(&file, DigestKind::Tiger).unwrap(), tiger);
+        cache.flush();
+
+        // Plant a wrong tree hash root to see that it is used.
+        let key = FileKey::from_metadata(&fs::metadata(&file).unwrap()).unwrap();
+        let mut backend = CacheFile::open(&cache_file).unwrap();
+        let mut digests = backend.get(&file, &key).unwrap();
+        assert_eq!(digests.tiger, Some(tiger.1));
+        digests.tree = Some(tiger_hash::digest(b"planted"));
+        backend.put(&file, &key, digests).unwrap();
+        backend.flush().unwrap();
+
+        let cache = Cache::open(&spec, "test").unwrap();
+        assert_eq!(cache.digest(&file, DigestKind::Tiger).unwrap(), tiger);
+        assert_eq!(
+            cache.digest(&file, DigestKind::Tree).unwrap(),
+            (3, tiger_hash::digest(b"planted"))
+        );
+
+        fs::write(&file, b"abcd").unwrap();
+        assert_eq!(
+            cache.digest(&file, DigestKind::Tree).unwrap(),
+            (4, tiger_hash::tree_hash(b"abcd"))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! Helpers shared by the command-line tools.
+
+// Each tool uses a different subset.
+#![allow(dead_code)]
+
+use std::num::NonZeroUsize;
+use std::thread;
+
+pub mod cache;
+#[path = "../../shared.rs"]
+mod shared;
+#[cfg(test)]
+#[path = "../../test_util/temp_dir.rs"]
+pub mod temp_dir;
+pub mod walk;
+
+#[allow(unused_imports)]
+pub(crate) use shared::run_parallel;
+
+/// Escapes backslashes and newlines in `name` the way `sha256sum` does,
+/// returning the line prefix that marks an escaped name.
+pub fn escape(name: &str) -> (&'static str, String) {
+    if !name.contains(['\\', '\n', '\r']) {
+        return ("", name.to_string());
+    }
+    let escaped = name
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    ("\\", escaped)
+}
+
+/// Reverses [`escape`]. Returns `None` for an unknown escape sequence.
+pub fn unescape(name: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+/// Quotes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Number of worker threads to use when none is given.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Outcome counts of checking a list of checksums.
+#[derive(Debug, Default)]
+pub struct CheckCounts {
+    pub verified: usize,
+    pub failed: usize,
+    pub unreadable: usize,
+    pub improper: usize,
+}
+
+impl CheckCounts {
+    /// Prints `sha256sum`-style warnings about everything that went wrong.
+    pub fn print_warnings(&self, program: &str) {
+        let warn = |count: usize, singular: &str, plural: &str| {
+            if count > 0 {
+                let what = if count == 1 { singular } else { plural };
+                eprintln!("{}: WARNING: {} {}", program, count, what);
+            }
+        };
+        warn(
+            self.improper,
+            "line is improperly formatted",
+            "lines are improperly formatted",
+        );
+        warn(
+            self.unreadable,
+            "listed file could not be read",
+            "listed files could not be read",
+        );
+        warn(
+            self.failed,
+            "computed checksum did NOT match",
+            "computed checksums did NOT match",
+        );
+    }
+
+    /// Whether every listed file was read and matched, and with `strict`,
+    /// every line was well formed.
+    pub fn passed(&self, strict: bool) -> bool {
+        self.failed == 0 && self.unreadable == 0 && !(strict && self.improper > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("plain name"), ("", "plain name".to_string()));
+        for name in ["back\\slash", "new\nline", "cr\r", "\\n"] {
+            let (prefix, escaped) = escape(name);
+            assert_eq!(prefix, "\\");
+            assert!(!escaped.contains('\n'));
+            assert_eq!(unescape(&escaped).as_deref(), Some(name));
+        }
+        assert_eq!(unescape("bad\\q"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let items: Vec<u64> = (0..100).collect();
+        for jobs in [1, 3, 16] {
+            let mut seen = Vec::new();
+            run_parallel(
+                &items,
+                jobs,
+                |&i| i * i,
+                |&i, square| {
+                    seen.push((i, square));
+                    Ok::<_, ()>(())
+                },
+            )
+            .unwrap();
+            assert_eq!(seen, items.iter().map(|&i| (i, i * i)).collect::<Vec<_>>());
+        }
+
+        let mut count = 0;
+        let result = run_parallel(
+            &items,
+            4,
+            |&i| i,
+            |&i, _| {
+                count += 1;
+                if i == 10 {
+                    Err(i)
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        assert_eq!((result, count), (Err(10), 11));
+        run_parallel(&[] as &[u8], 4, |_| (), |_, ()| Ok::<_, ()>(())).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+//! Recursive directory walking with exclude patterns.
+
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub(crate) use super::shared::file_id;
+
+/// How [`walk`] treats symbolic links found below a root. Roots given as
+/// symbolic links are always followed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Leave symbolic links out.
+    #[default]
+    Skip,
+    /// Treat symbolic links like their targets, skipping directories that
+    /// would lead back into one of their ancestors.
+    Follow,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    pub symlinks: Symlinks,
+    /// Glob patterns of paths to leave out, see [`is_excluded`].
+    pub excludes: Vec<String>,
+}
+
+/// A regular file found by [`walk`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalkEntry {
+    /// Path of the file, starting with the root it was found under.
+    pub path: PathBuf,
+    /// Path relative to the root, with `/` separators. Empty if the root
+    /// itself is the file.
+    pub relative: String,
+    /// Size when the file was found.
+    pub size: u64,
+    /// Device and inode number, shared by all hard links to a file. `None`
+    /// where the platform doesn't provide them.
+    pub file_id: Option<(u64, u64)>,
+}
+
+/// Lists the regular files under `root`, or `root` itself if it is a file.
+///
+/// Entries come out sorted by path, comparing one component at a time.
+/// Special files such as FIFOs and devices are left out. Errors on single
+/// entries are passed to `on_error` and the walk goes on.
+pub fn walk(
+    root: &Path,
+    options: &WalkOptions,
+    on_error: &mut dyn FnMut(&Path, io::Error),
+) -> Vec<WalkEntry> {
+    let mut walker = Walker {
+        options,
+        on_error,
+        ancestors: Vec::new(),
+        files: Vec::new(),
+    };
+    match fs::metadata(root) {
+        Ok(metadata) => walker.visit(root.to_path_buf(), String::new(), metadata),
+        Err(e) => (walker.on_error)(root, e),
+    }
+    walker.files
+}
+
+/// Whether the path `relative` to a walk root is excluded by one of
+/// `patterns`.
+///
+/// Patterns containing a `/` are matched against the whole relative path,
+/// others against the last component, so `*.o` excludes object files at any
+/// depth and `build/*.o` only those directly in `build`. Excluding a
+/// directory excludes everything below it.
+pub fn is_excluded(relative: &str, patterns: &[String]) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    patterns.iter().any(|pattern| {
+        if pattern.contains('/') {
+            glob_match(pattern.trim_start_matches("./"), relative)
+        } else {
+            glob_match(pattern, name)
+        }
+    })
+}
+
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    on_error: &'a mut dyn FnMut(&Path, io::Error),
+    /// Canonical paths of the directories being visited, when following
+    /// symbolic links.
+    ancestors: Vec<PathBuf>,
+    files: Vec<WalkEntry>,
+}
+
+impl Walker<'_> {
+    fn visit(&mut self, path: PathBuf, relative: String, metadata: Metadata) {
+        if metadata.is_file() {
+            self.files.push(WalkEntry {
+                size: metadata.len(),
+                file_id: file_id(&metadata),
+                path,
+                relative,
+            });
+        } else if metadata.is_dir() {
+            self.visit_dir(path, relative);
+        }
+    }
+
+    fn visit_dir(&mut self, path: PathBuf, relative: String) {
+        let follow = self.options.symlinks == Symlinks::Follow;
+        if follow {
+            match fs::canonicalize(&path) {
+                Ok(canonical) if self.ancestors.contains(&canonical) => {
+                    let loop_error = io::Error::other("directory loop, not descending");
+                    return (self.on_error)(&path, loop_error);
+                }
+                Ok(canonical) => self.ancestors.push(canonical),
+                Err(e) => return (self.on_error)(&path, e),
+            }
+        }
+
+        let mut children =
+            match fs::read_dir(&path).and_then(|dir| dir.collect::<Result<Vec<_>, _>>()) {
+                Ok(children) => children,
+                Err(e) => {
+                    (self.on_error)(&path, e);
+                    Vec::new()
+                }
+            };
+        children.sort_by_key(|child| child.file_name());
+
+        for child in children {
+            let name = child.file_name();
+            let name = name.to_string_lossy();
+            let child_relative = if relative.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{}/{}", relative, name)
+            };
+            if is_excluded(&child_relative, &self.options.excludes) {
+                continue;
+            }
+            let child_path = child.path();
+            let metadata = if follow {
+                fs::metadata(&child_path)
+            } else {
+                fs::symlink_metadata(&child_path)
+            };
+            match metadata {
+                Ok(metadata) => self.visit(child_path, child_relative, metadata),
+                Err(e) => (self.on_error)(&child_path, e),
+            }
+        }
+
+        if follow {
+            self.ancestors.pop();
+        }
+    }
+}
+
+/// Matches `text` against a shell glob: `*` matches any run of characters
+/// except `/`, `?` any single character except `/`, `[...]` a character
+/// class (negated with `!` or `^`, with `a-z` ranges) and `\` escapes the
+/// next character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: pattern after it, text it covers.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        if let Some(next) = match_char(&pattern, p, text[t]) {
+            p = next;
+            t += 1;
+            continue;
+        }
+        match star {
+            // Let the `*` cover one more character.
+            Some((star_p, star_t)) if text[star_t] != '/' => {
+                star = Some((star_p, star_t + 1));
+                p = star_p;
+                t = star_t + 1;
+            }
+            _ => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the pattern element at `p`, returning the index of
+/// the next element on a match.
+fn match_char(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '*' => None,
+        '?' => (c != '/').then_some(p + 1),
+        '\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        '[' => match match_class(pattern, p + 1, c) {
+            Some((matched, next)) => matched.then_some(next),
+            // An unterminated class is a literal `[`.
+            None => (c == '[').then_some(p + 1),
+        },
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Matches `c` against the class starting at `p`, just after the `[`.
+/// Returns whether it matched and the index after the closing `]`, or `None`
+/// if the class is not terminated.
+fn match_class(pattern: &[char], mut p: usize, c: char) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.get(p), Some('!' | '^'));
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let low = *pattern.get(p)?;
+        if low == ']' && !first {
+            return Some((matched != negated && c != '/', p + 1));
+        }
+        first = false;
+        match pattern.get(p + 1..p + 3) {
+            Some(&['-', high]) if high != ']' => {
+                matched |= (low..=high).contains(&c);
+                p += 3;
+            }
+            _ => {
+                matched |= low == c;
+                p += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_dir::temp_dir;
+
+    #[test]
+    fn test_glob_match() {
+        let cases = [
+            ("*.o", "main.o", true),
+            ("*.o", "main.c", false),
+            ("*.o", "dir/main.o", false),
+            ("*", "", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("?.txt", "a.txt", true),
+            ("?.txt", "ab.txt", false),
+            ("build/*", "build/x", true),
+            ("build/*", "build/x/y", false),
+            ("*/x", "build/x", true),
+            ("[abc]", "b", true),
+            ("[!abc]", "b", false),
+            ("[^abc]", "d", true),
+            ("[a-c]x", "cx", true),
+            ("[a-c]x", "dx", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[abc", "[abc", true),
+            ("\\*", "*", true),
+            ("\\*", "x", false),
+            (".git", ".git", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, text),
+                expected,
+                "{:?} {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let patterns = ["*.o".to_string(), "docs/tmp".to_string()];
+        assert!(is_excluded("a.o", &patterns));
+        assert!(is_excluded("src/deep/a.o", &patterns));
+        assert!(is_excluded("docs/tmp", &patterns));
+        assert!(!is_excluded("src/docs/tmp", &patterns));
+        assert!(!is_excluded("a.c", &patterns));
+    }
+
+    fn relative_paths(root: &Path, options: &WalkOptions) -> (Vec<String>, usize) {
+        let mut errors = 0;
+        let files = walk(root, options, &mut |_, _| errors += 1);
+        (
+            files.into_iter().map(|entry| entry.relative).collect(),
+            errors,
+        )
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = temp_dir("order");
+        for path in ["b/2", "b/1", "a", "c/d/e", "b.o"] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"x").unwrap();
+        }
+        fs::create_dir(root.join("empty")).unwrap();
+
+        let options = WalkOptions::default();
+        assert_eq!(
+            relative_paths(&root, &options),
+            (
+                vec!["a", "b/1", "b/2", "b.o", "c/d/e"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                0
+            )
+        );
+        let options = WalkOptions {
+            excludes: vec!["*.o".to_string(), "c/d".to_string()],
+            ..WalkOptions::default()
+        };
+        assert_eq!(relative_paths(&root, &options).0, ["a", "b/1", "b/2"]);
+        assert_eq!(relative_paths(&root.join("a"), &options).0, [""]);
+
+        let missing = root.join("missing");
+        assert_eq!(relative_paths(&missing, &options), (vec![], 1));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links() {
+        use std::os::unix::fs::symlink;
+
+        let root = temp_dir("links");
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file"), b"x").unwrap();
+        fs::hard_link(root.join("dir/file"), root.join("hard")).unwrap();
+        symlink("dir/file", root.join("file-link")).unwrap();
+        symlink("..", root.join("dir/loop")).unwrap();
+        symlink("nowhere", root.join("dangling")).unwrap();
+
+        let skip = WalkOptions::default();
+        let files = walk(&root, &skip, &mut |path, e| panic!("{:?}: {}", path, e));
+        let relative: Vec<_> = files.iter().map(|entry| entry.relative.as_str()).collect();
+        assert_eq!(relative, ["dir/file", "hard"]);
+        assert_eq!(files[0].file_id, files[1].file_id);
+
+        let follow = WalkOptions {
+            symlinks: Symlinks::Follow,
+            ..WalkOptions::default()
+        };
+        let mut errors = Vec::new();
+        let files = walk(&root, &follow, &mut |path, _| {
+            errors.push(path.strip_prefix(&root).unwrap().to_path_buf())
+        });
+        let relative: Vec<_> = files.iter().map(|entry| entry.relative.as_str()).collect();
+        assert_eq!(relative, ["dir/file", "file-link", "hard"]);
+        assert_eq!(errors, [Path::new("dangling"), Path::new("dir/loop")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
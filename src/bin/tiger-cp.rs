@@ -0,0 +1,243 @@
+//! `tiger-cp`: copy files and prove the copies match by their Tiger digests.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use tiger_hash::{copy_verified, DigestOrder, TigerDigest};
+
+use crate::common::escape;
+
+mod common;
+
+const USAGE: &str = "\
+Usage: tiger-cp [OPTION]... SOURCE DEST
+  or:  tiger-cp [OPTION]... SOURCE... DIRECTORY
+Copy each SOURCE file to DEST, or into DIRECTORY, hashing it on the way.
+Each copy is synced to storage, read back and compared with the source by
+its Tiger digest, and its digest printed as `tigersum` would.
+
+  -s, --sidecar  also write the digest to DEST.tiger, which
+                 `tigersum -c` can check
+  -q, --quiet    don't print the digests
+  -h, --help     print this help
+
+A copy that doesn't read back the same is reported with both digests.
+Exit status is 0 if every file was copied and verified; 1 otherwise.";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    quiet: bool,
+    sidecar: bool,
+    sources: Vec<String>,
+    target: String,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let (mut quiet, mut sidecar) = (false, false);
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--sidecar" => sidecar = true,
+            "-q" | "--quiet" => quiet = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => {
+                paths.extend(args);
+                break;
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unrecognized option {:?}", arg))
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let Some(target) = paths.pop() else {
+        return Err("missing file operand".to_string());
+    };
+    if paths.is_empty() {
+        return Err(format!(
+            "missing destination file operand after {:?}",
+            target
+        ));
+    }
+    Ok(Command::Run(Options {
+        quiet,
+        sidecar,
+        sources: paths,
+        target,
+    }))
+}
+
+/// Pairs each source with its destination: `target` itself for a single
+/// source, otherwise a file of the same name in the directory `target`.
+fn destinations(options: &Options) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let target = Path::new(&options.target);
+    if !target.is_dir() {
+        if options.sources.len() > 1 {
+            return Err(format!("target {:?} is not a directory", options.target));
+        }
+        return Ok(vec![(
+            PathBuf::from(&options.sources[0]),
+            target.to_path_buf(),
+        )]);
+    }
+    options
+        .sources
+        .iter()
+        .map(|source| match Path::new(source).file_name() {
+            Some(name) => Ok((PathBuf::from(source), target.join(name))),
+            None => Err(format!("cannot copy {:?} into a directory", source)),
+        })
+        .collect()
+}
+
+fn to_hex(digest: &TigerDigest) -> String {
+    digest.to_hex(DigestOrder::Canonical).to_ascii_lowercase()
+}
+
+/// Writes `destination.tiger` listing the digest of `destination` by its
+/// file name.
+fn write_sidecar(destination: &Path, digest: &TigerDigest) -> io::Result<()> {
+    let name = destination
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let (prefix, name) = escape(&name);
+    let mut sidecar = OsString::from(destination.as_os_str());
+    sidecar.push(".tiger");
+    fs::write(sidecar, format!("{}{}  {}\n", prefix, to_hex(digest), name))
+}
+
+fn run(options: &Options) -> io::Result<bool> {
+    let pairs = match destinations(options) {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            eprintln!("tiger-cp: {}", e);
+            return Ok(false);
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    let mut ok = true;
+    for (source, destination) in pairs {
+        let digest = match copy_verified(&source, &destination) {
+            Ok(digest) => digest,
+            Err(e) => {
+                eprintln!("tiger-cp: {}", e);
+                ok = false;
+                continue;
+            }
+        };
+        if options.sidecar {
+            if let Err(e) = write_sidecar(&destination, &digest) {
+                eprintln!("tiger-cp: {}.tiger: {}", destination.display(), e);
+                ok = false;
+            }
+        }
+        if !options.quiet {
+            let (prefix, name) = escape(&destination.display().to_string());
+            writeln!(stdout, "{}{}  {}", prefix, to_hex(&digest), name)?;
+        }
+    }
+    Ok(ok)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!(
+                "tiger-cp: {}\nTry 'tiger-cp --help' for more information.",
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // Most likely a closed pipe on stdout.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("tiger-cp: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_dir::temp_dir;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(options) => Ok(options),
+            Command::Help => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse(&["-s", "a", "b", "--", "-dir"]).unwrap(),
+            Options {
+                quiet: false,
+                sidecar: true,
+                sources: vec!["a".to_string(), "b".to_string()],
+                target: "-dir".to_string(),
+            }
+        );
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["only"]).is_err());
+        assert!(parse(&["-r", "a", "b"]).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_destinations() {
+        let dir = temp_dir("dest");
+        let dir_name = dir.display().to_string();
+
+        let options = parse(&["x/a", "b", &dir_name]).unwrap();
+        assert_eq!(
+            destinations(&options).unwrap(),
+            [
+                (PathBuf::from("x/a"), dir.join("a")),
+                (PathBuf::from("b"), dir.join("b"))
+            ]
+        );
+        let file = dir.join("new").display().to_string();
+        let options = parse(&["a", &file]).unwrap();
+        assert_eq!(
+            destinations(&options).unwrap(),
+            [(PathBuf::from("a"), dir.join("new"))]
+        );
+        assert!(destinations(&parse(&["a", "b", &file]).unwrap()).is_err());
+        assert!(destinations(&parse(&["..", &dir_name]).unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_write_sidecar() {
+        let dir = temp_dir("sidecar");
+        write_sidecar(&dir.join("archive.tar"), &tiger_hash::digest(b"abc")).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("archive.tar.tiger")).unwrap(),
+            "2aab1484e8c158f2bfb8c5ff41b57a525129131c957b5f93  archive.tar\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
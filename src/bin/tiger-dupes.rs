@@ -0,0 +1,396 @@
+//! `tiger-dupes`: find files with the same contents, and optionally replace
+//! them with hard links.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use tiger_hash::{find_duplicates, DigestKind, DigestOrder, DuplicateGroup};
+
+use crate::common::walk::{file_id, walk, Symlinks, WalkOptions};
+use crate::common::{default_jobs, escape, json_string};
+
+mod common;
+
+const USAGE: &str = "\
+Usage: tiger-dupes [OPTION]... [PATH]...
+List the files under each PATH that have the same contents, one group per
+paragraph. PATH defaults to the current directory.
+
+  -j, --jobs N           read up to N files at once (default: one per CPU)
+      --json             write the groups as JSON
+      --link             replace every file of a group with a hard link to
+                         the first one
+      --tth              confirm with Tiger tree hashes instead of Tiger
+                         digests
+  -L, --follow           follow symbolic links (default: leave them out)
+  -x, --exclude PATTERN  leave out files and directories matching PATTERN;
+                         may be given more than once
+  -h, --help             print this help
+
+Files are first grouped by size, then by a digest of their first and last
+4 KiB, and only reported as duplicates once the digests of their whole
+contents match. Empty files are left out. Hard links to the same file
+count as one file, so a group always holds at least two different files.
+
+A PATTERN containing `/` is matched against the path below PATH, any other
+PATTERN against file and directory names. --link cannot be combined with
+--follow. It hashes every file again just before replacing it, and leaves
+files alone whose contents changed since they were compared.
+
+Exit status is 0 if every file was read and, with --link, replaced; 1
+otherwise.";
+
+#[derive(Debug)]
+struct Options {
+    kind: DigestKind,
+    jobs: usize,
+    json: bool,
+    link: bool,
+    walk: WalkOptions,
+    paths: Vec<String>,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        kind: DigestKind::Tiger,
+        jobs: default_jobs(),
+        json: false,
+        link: false,
+        walk: WalkOptions::default(),
+        paths: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| match value.clone() {
+            Some(value) => Ok(value),
+            None => args
+                .next()
+                .ok_or(format!("option --{} needs a value", name)),
+        };
+        match flag {
+            "-j" | "--jobs" => {
+                let jobs = value("jobs")?;
+                options.jobs = match jobs.parse() {
+                    Ok(jobs) if jobs > 0 => jobs,
+                    _ => return Err(format!("invalid number of jobs {:?}", jobs)),
+                };
+            }
+            "--json" => options.json = true,
+            "--link" => options.link = true,
+            "--tth" => options.kind = DigestKind::Tree,
+            "-L" | "--follow" => options.walk.symlinks = Symlinks::Follow,
+            "-x" | "--exclude" => options.walk.excludes.push(value("exclude")?),
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => {
+                options.paths.extend(args);
+                break;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unrecognized option {:?}", arg)),
+            _ => options.paths.push(arg),
+        }
+    }
+    if options.link && options.walk.symlinks == Symlinks::Follow {
+        return Err("--link cannot be combined with --follow".to_string());
+    }
+    if options.paths.is_empty() {
+        options.paths.push(".".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// The digest of a group as written in the JSON output.
+fn digest_field(group: &DuplicateGroup, kind: DigestKind) -> (&'static str, String) {
+    match kind {
+        DigestKind::Tiger => (
+            "tiger",
+            group
+                .digest
+                .to_hex(DigestOrder::Canonical)
+                .to_ascii_lowercase(),
+        ),
+        DigestKind::Tree => ("tth", group.digest.to_base32()),
+    }
+}
+
+fn write_groups(out: &mut impl Write, groups: &[DuplicateGroup]) -> io::Result<()> {
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        for path in &group.paths {
+            let (prefix, path) = escape(&path.display().to_string());
+            writeln!(out, "{}{}", prefix, path)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_groups_json(
+    out: &mut impl Write,
+    groups: &[DuplicateGroup],
+    kind: DigestKind,
+) -> io::Result<()> {
+    writeln!(out, "{{\"groups\": [")?;
+    for (i, group) in groups.iter().enumerate() {
+        let separator = if i + 1 < groups.len() { "," } else { "" };
+        let (name, digest) = digest_field(group, kind);
+        let paths: Vec<String> = group
+            .paths
+            .iter()
+            .map(|path| json_string(&path.display().to_string()))
+            .collect();
+        writeln!(
+            out,
+            "  {{\"size\": {}, \"{}\": \"{}\", \"paths\": [{}]}}{}",
+            group.size,
+            name,
+            digest,
+            paths.join(", "),
+            separator
+        )?;
+    }
+    writeln!(out, "]}}")
+}
+
+/// Whether `path` is still a regular file with the size and digest of
+/// `group`, hashing it again.
+fn unchanged(path: &Path, group: &DuplicateGroup, kind: DigestKind) -> io::Result<bool> {
+    let file = File::open(path)?;
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(metadata.is_file()
+        && file_id(&metadata) == file_id(&file.metadata()?)
+        && kind.digest_file(&file)? == (group.size, group.digest))
+}
+
+/// Replaces `path`, a file of `group`, with a hard link to `target`.
+///
+/// `path` is hashed again first and left alone if its contents changed since
+/// it was compared. The link is made under a temporary name next to `path`
+/// and renamed over it, so `path` never goes missing.
+fn replace_with_link(
+    target: &Path,
+    path: &Path,
+    group: &DuplicateGroup,
+    kind: DigestKind,
+) -> io::Result<()> {
+    if !unchanged(path, group, kind)? {
+        return Err(io::Error::other(
+            "changed since it was compared, not replacing",
+        ));
+    }
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".tiger-dupes-{}", std::process::id()));
+    let temporary = path.with_file_name(name);
+    fs::hard_link(target, &temporary)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+/// Replaces every file of every group with a hard link to its first file.
+/// Returns whether all could be replaced.
+fn link_groups(groups: &[DuplicateGroup], kind: DigestKind) -> bool {
+    let (mut ok, mut linked) = (true, 0);
+    for group in groups {
+        let target = &group.paths[0];
+        match unchanged(target, group, kind) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!(
+                    "tiger-dupes: {}: changed since it was compared",
+                    target.display()
+                );
+                ok = false;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("tiger-dupes: {}: {}", target.display(), e);
+                ok = false;
+                continue;
+            }
+        }
+        let target_id = fs::metadata(target)
+            .ok()
+            .and_then(|metadata| file_id(&metadata));
+        for path in &group.paths[1..] {
+            let id = fs::metadata(path)
+                .ok()
+                .and_then(|metadata| file_id(&metadata));
+            if id.is_some() && id == target_id {
+                continue;
+            }
+            match replace_with_link(target, path, group, kind) {
+                Ok(()) => linked += 1,
+                Err(e) => {
+                    eprintln!("tiger-dupes: {}: {}", path.display(), e);
+                    ok = false;
+                }
+            }
+        }
+    }
+    eprintln!("tiger-dupes: replaced {} files with hard links", linked);
+    ok
+}
+
+fn run(options: &Options) -> io::Result<bool> {
+    let mut ok = true;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for root in &options.paths {
+        let mut on_error = |path: &Path, e: io::Error| {
+            eprintln!("tiger-dupes: {}: {}", path.display(), e);
+            ok = false;
+        };
+        paths.extend(
+            walk(Path::new(root), &options.walk, &mut on_error)
+                .into_iter()
+                .map(|entry| entry.path),
+        );
+    }
+
+    let groups = find_duplicates(&paths, options.kind, options.jobs, |path, e| {
+        eprintln!("tiger-dupes: {}: {}", path.display(), e);
+        ok = false;
+    });
+    let mut stdout = io::stdout().lock();
+    if options.json {
+        write_groups_json(&mut stdout, &groups, options.kind)?;
+    } else {
+        write_groups(&mut stdout, &groups)?;
+    }
+    stdout.flush()?;
+    if options.link {
+        ok &= link_groups(&groups, options.kind);
+    }
+    Ok(ok)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!(
+                "tiger-dupes: {}\nTry 'tiger-dupes --help' for more information.",
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // Most likely a closed pipe on stdout.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("tiger-dupes: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::temp_dir::temp_dir;
+    use tiger_hash::{digest, tree_hash};
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(options) => Ok(options),
+            Command::Help => unreachable!(),
+        }
+    }
+
+    fn group(contents: &[u8], paths: &[&str]) -> DuplicateGroup {
+        DuplicateGroup {
+            size: contents.len() as u64,
+            digest: digest(contents),
+            paths: paths.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse(&["--tth", "-j", "2", "--link", "-x", "*.o", "a"]).unwrap();
+        assert_eq!(options.kind, DigestKind::Tree);
+        assert_eq!((options.jobs, options.link), (2, true));
+        assert_eq!(options.walk.excludes, ["*.o"]);
+        assert_eq!(options.paths, ["a"]);
+        assert_eq!(parse(&[]).unwrap().paths, ["."]);
+        assert!(parse(&["--link", "-L"]).is_err());
+        assert!(parse(&["-j", "0"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_write_groups() {
+        let groups = [group(b"abc", &["a", "b"]), group(b"x", &["new\nline", "c"])];
+        let mut out = Vec::new();
+        write_groups(&mut out, &groups).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n\n\\new\\nline\nc\n");
+
+        let mut out = Vec::new();
+        write_groups_json(&mut out, &groups[..1], DigestKind::Tiger).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"groups\": [\n  {\"size\": 3, \"tiger\": \
+             \"2aab1484e8c158f2bfb8c5ff41b57a525129131c957b5f93\", \"paths\": [\"a\", \"b\"]}\n]}\n"
+        );
+
+        let mut tree = group(b"abc", &["a", "b"]);
+        tree.digest = tree_hash(b"abc");
+        assert_eq!(
+            digest_field(&tree, DigestKind::Tree),
+            ("tth", "ASD4UJSEH5M47PDYB46KBTSQTSGDKLBHYXOMUIA".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_link_groups() {
+        let root = temp_dir("link");
+        for name in ["a", "b", "c", "d"] {
+            fs::write(root.join(name), b"same").unwrap();
+        }
+        fs::hard_link(root.join("a"), root.join("a2")).unwrap();
+        let paths: Vec<PathBuf> = ["a", "a2", "b", "c", "d"]
+            .iter()
+            .map(|name| root.join(name))
+            .collect();
+
+        let groups = find_duplicates(&paths, DigestKind::Tiger, 2, |path, e| {
+            panic!("{:?}: {}", path, e)
+        });
+        assert_eq!(groups.len(), 1);
+        // One file grows, another changes but keeps its size.
+        fs::write(root.join("c"), b"longer now").unwrap();
+        fs::write(root.join("d"), b"diff").unwrap();
+        assert!(!link_groups(&groups, DigestKind::Tiger));
+
+        let id = |name: &str| file_id(&fs::metadata(root.join(name)).unwrap());
+        assert_eq!(id("b"), id("a"));
+        assert_ne!(id("c"), id("a"));
+        assert_ne!(id("d"), id("a"));
+        assert_eq!(fs::read(root.join("c")).unwrap(), b"longer now");
+        assert_eq!(fs::read(root.join("d")).unwrap(), b"diff");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 5);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
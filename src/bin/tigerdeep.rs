@@ -0,0 +1,894 @@
+//! `tigerdeep`: write or audit hashdeep-style manifests of Tiger digests.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use tiger_hash::{digest_dir, DigestKind, DigestOrder, TigerDigest};
+
+use crate::common::cache::{Cache, CacheSpec};
+use crate::common::walk::{is_excluded, walk, Symlinks, WalkEntry, WalkOptions};
+use crate::common::{default_jobs, escape, json_string, run_parallel};
+
+mod common;
+
+const USAGE: &str = "\
+Usage: tigerdeep [OPTION]... [PATH]...
+  or:  tigerdeep --watch [--tth] [--json] [PATH]
+Write a hashdeep-compatible manifest (size,tiger,filename) of the files
+under each PATH, or audit them against one. With --watch, keep watching
+PATH and report files as their contents change. PATH defaults to the
+current directory.
+
+  -a, --audit MANIFEST   compare the files with MANIFEST instead
+      --cache CACHE      reuse digests recorded in the file CACHE, and record
+                         new ones, for files whose size, times and inode are
+                         unchanged
+      --cache-xattr      a weaker cache recording digests in an extended
+                         attribute of each file, which can't check the
+                         status change time (Linux only; see below)
+  -d, --digest           print one digest of each PATH's whole tree instead
+  -j, --jobs N           hash up to N files at once (default: one per CPU)
+      --json             write the manifest or audit report as JSON
+  -L, --follow           follow symbolic links (default: leave them out)
+  -x, --exclude PATTERN  leave out files and directories matching PATTERN;
+                         may be given more than once
+  -v, --verbose          also list matched files in the audit report
+      --watch            watch PATH and print a line for every file created,
+                         changed or removed, with its old and new digests
+                         (Linux only)
+      --tth              with --watch, use TTH root digests instead of Tiger
+  -h, --help             print this help
+
+A PATTERN containing `/` is matched against the path below PATH, any other
+PATTERN against file and directory names. Hard links to the same file are
+hashed once.
+
+A cache trusts file metadata, so a file damaged without its metadata
+changing keeps its recorded digest; leave the cache out to catch that.
+--cache-xattr trusts even less of it: setting the attribute changes the
+status change time, so a file rewritten and given back its old size and
+modification time keeps its recorded digest too. Don't use it for audits
+that must catch tampering; it warns when used.
+
+--digest hashes names, contents, executable bits and symbolic link targets
+in a canonical order, ignoring timestamps and ownership, and cannot be
+combined with other options but --jobs. See `tiger_hash::digest_dir` for
+the exact format.
+
+--watch rehashes a file once it has been left alone for half a second, so
+a burst of writes is reported once. Each line reads `EVENT OLD NEW  PATH`,
+where EVENT is new, changed or missing and an absent digest is `-`; with
+--json each event is a JSON object on a line of its own. Tiger digests are
+written in hex and TTH digests in base32. Watch mode runs until it is
+interrupted, and cannot be combined with the audit, cache, digest, exclude,
+follow or verbose options.
+
+The audit sorts every file into one of:
+  matched  listed with the same size and digest
+  changed  listed, with a different size or digest
+  moved    not listed, but has the size and digest of a listed file that
+           is gone
+  new      neither its path nor its contents are listed
+  missing  listed, but not found
+
+Exit status is 0 if every file was hashed and, with --audit, all files
+matched; 1 otherwise.";
+
+const MANIFEST_MAGIC: &str = "%%%% HASHDEEP-1.0";
+
+#[derive(Debug)]
+struct Options {
+    audit: Option<String>,
+    cache: Option<CacheSpec>,
+    digest: bool,
+    jobs: usize,
+    json: bool,
+    tth: bool,
+    verbose: bool,
+    walk: WalkOptions,
+    watch: bool,
+    paths: Vec<String>,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        audit: None,
+        cache: None,
+        digest: false,
+        jobs: default_jobs(),
+        json: false,
+        tth: false,
+        verbose: false,
+        walk: WalkOptions::default(),
+        watch: false,
+        paths: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| match value.clone() {
+            Some(value) => Ok(value),
+            None => args
+                .next()
+                .ok_or(format!("option --{} needs a value", name)),
+        };
+        match flag {
+            "-a" | "--audit" => options.audit = Some(value("audit")?),
+            "--cache" => options.cache = Some(CacheSpec::File(value("cache")?)),
+            "--cache-xattr" => options.cache = Some(CacheSpec::Xattr),
+            "-d" | "--digest" => options.digest = true,
+            "-j" | "--jobs" => {
+                let jobs = value("jobs")?;
+                options.jobs = match jobs.parse() {
+                    Ok(jobs) if jobs > 0 => jobs,
+                    _ => return Err(format!("invalid number of jobs {:?}", jobs)),
+                };
+            }
+            "-x" | "--exclude" => options.walk.excludes.push(value("exclude")?),
+            "--json" => options.json = true,
+            "-L" | "--follow" => options.walk.symlinks = Symlinks::Follow,
+            "--tth" => options.tth = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "--watch" => options.watch = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => {
+                options.paths.extend(args);
+                break;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unrecognized option {:?}", arg)),
+            _ => options.paths.push(arg),
+        }
+    }
+    let walk_options = !options.walk.excludes.is_empty() || options.walk.symlinks != Symlinks::Skip;
+    if options.digest
+        && (options.audit.is_some()
+            || options.cache.is_some()
+            || options.json
+            || options.verbose
+            || walk_options)
+    {
+        return Err("--digest cannot be combined with other options".to_string());
+    }
+    if options.watch {
+        if options.audit.is_some()
+            || options.cache.is_some()
+            || options.digest
+            || options.verbose
+            || walk_options
+        {
+            return Err("--watch cannot be combined with that option".to_string());
+        }
+        if options.paths.len() > 1 {
+            return Err("--watch takes a single PATH".to_string());
+        }
+    } else if options.tth {
+        return Err("--tth only applies to --watch".to_string());
+    }
+    if options.paths.is_empty() {
+        options.paths.push(".".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// One file of a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Record {
+    size: u64,
+    digest: TigerDigest,
+    path: String,
+}
+
+/// Joins a walk root as given on the command line with a path below it.
+fn display_path(root: &str, relative: &str) -> String {
+    if relative.is_empty() {
+        root.to_string()
+    } else {
+        format!("{}/{}", root.trim_end_matches('/'), relative)
+    }
+}
+
+fn hash_file(path: &Path, cache: Option<&Cache>) -> io::Result<(u64, TigerDigest)> {
+    match cache {
+        Some(cache) => cache.digest(path, DigestKind::Tiger),
+        None => DigestKind::Tiger.digest_file(&File::open(path)?),
+    }
+}
+
+/// Walks and hashes every root. Returns the records in walk order and the
+/// paths that could not be walked or read; errors are printed as they
+/// happen.
+fn collect(options: &Options, cache: Option<&Cache>) -> (Vec<Record>, HashSet<String>) {
+    let mut entries: Vec<(String, WalkEntry)> = Vec::new();
+    let mut failed = HashSet::new();
+    for root in &options.paths {
+        let mut on_error = |path: &Path, e: io::Error| {
+            eprintln!("tigerdeep: {}: {}", path.display(), e);
+            failed.insert(path.display().to_string());
+        };
+        for entry in walk(Path::new(root), &options.walk, &mut on_error) {
+            entries.push((display_path(root, &entry.relative), entry));
+        }
+    }
+
+    // Hard links share a file id and are only hashed once.
+    let mut first_link: HashMap<(u64, u64), usize> = HashMap::new();
+    let mut to_hash = Vec::new();
+    let sources: Vec<usize> = (0..entries.len())
+        .map(|i| match entries[i].1.file_id {
+            Some(id) => *first_link.entry(id).or_insert_with(|| {
+                to_hash.push(i);
+                i
+            }),
+            None => {
+                to_hash.push(i);
+                i
+            }
+        })
+        .collect();
+
+    let mut hashes = HashMap::new();
+    let _ = run_parallel(
+        &to_hash,
+        options.jobs,
+        |&i| hash_file(&entries[i].1.path, cache),
+        |&i, result| {
+            match result {
+                Ok(hash) => {
+                    hashes.insert(i, hash);
+                }
+                Err(e) => eprintln!("tigerdeep: {}: {}", entries[i].1.path.display(), e),
+            }
+            Ok::<_, ()>(())
+        },
+    );
+
+    let mut records = Vec::new();
+    for ((path, _), source) in entries.into_iter().zip(sources) {
+        match hashes.get(&source) {
+            Some(&(size, digest)) => records.push(Record { size, digest, path }),
+            None => {
+                failed.insert(path);
+            }
+        }
+    }
+    (records, failed)
+}
+
+fn to_hex(digest: &TigerDigest) -> String {
+    digest.to_hex(DigestOrder::Canonical).to_ascii_lowercase()
+}
+
+/// Writes `records` as a hashdeep manifest. Paths containing a newline
+/// can't be represented and are reported instead. Returns whether all
+/// records were written.
+fn write_manifest(out: &mut impl Write, records: &[Record], invocation: &str) -> io::Result<bool> {
+    let cwd = std::env::current_dir().map_or_else(|_| "?".into(), |cwd| cwd.display().to_string());
+    writeln!(out, "{}", MANIFEST_MAGIC)?;
+    writeln!(out, "%%%% size,tiger,filename")?;
+    writeln!(out, "## Invoked from: {}", cwd)?;
+    writeln!(out, "## $ {}", invocation)?;
+    writeln!(out, "##")?;
+    let mut ok = true;
+    for record in records {
+        if record.path.contains(['\n', '\r']) {
+            eprintln!(
+                "tigerdeep: {:?}: a file name with a line break can't be listed",
+                record.path
+            );
+            ok = false;
+            continue;
+        }
+        writeln!(
+            out,
+            "{},{},{}",
+            record.size,
+            to_hex(&record.digest),
+            record.path
+        )?;
+    }
+    Ok(ok)
+}
+
+fn write_manifest_json(out: &mut impl Write, records: &[Record]) -> io::Result<()> {
+    writeln!(out, "{{\"files\": [")?;
+    for (i, record) in records.iter().enumerate() {
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        writeln!(
+            out,
+            "  {{\"path\": {}, \"size\": {}, \"tiger\": \"{}\"}}{}",
+            json_string(&record.path),
+            record.size,
+            to_hex(&record.digest),
+            separator
+        )?;
+    }
+    writeln!(out, "]}}")
+}
+
+/// Reads a hashdeep manifest. Besides `tiger`, the columns may list other
+/// hashes, which are ignored.
+fn parse_manifest(reader: impl BufRead) -> Result<Vec<Record>, String> {
+    let mut records = Vec::new();
+    // Column indices of size and tiger, and the column count.
+    let mut columns: Option<(usize, usize, usize)> = None;
+    let mut seen_magic = false;
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        let error = |message: &str| format!("line {}: {}", line_no + 1, message);
+
+        if line == MANIFEST_MAGIC {
+            seen_magic = true;
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("%%%% ") {
+            let names: Vec<&str> = header.split(',').collect();
+            let find = |name| names.iter().position(|&column| column == name);
+            match (find("size"), find("tiger"), names.last()) {
+                (Some(size), Some(tiger), Some(&"filename")) => {
+                    columns = Some((size, tiger, names.len()))
+                }
+                _ => return Err(error("expected size, tiger and filename columns")),
+            }
+            continue;
+        }
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        let (Some((size_column, tiger_column, count)), true) = (columns, seen_magic) else {
+            return Err(error("not a hashdeep manifest"));
+        };
+        let fields: Vec<&str> = line.splitn(count, ',').collect();
+        if fields.len() != count {
+            return Err(error("too few fields"));
+        }
+        let size = fields[size_column]
+            .parse()
+            .map_err(|_| error("invalid size"))?;
+        let digest = TigerDigest::from_hex(fields[tiger_column], DigestOrder::Canonical)
+            .map_err(|e| error(&e.to_string()))?;
+        records.push(Record {
+            size,
+            digest,
+            path: fields[count - 1].to_string(),
+        });
+    }
+    if !seen_magic {
+        return Err("not a hashdeep manifest".to_string());
+    }
+    Ok(records)
+}
+
+/// Result of comparing the files found with a manifest.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Audit {
+    matched: Vec<String>,
+    changed: Vec<String>,
+    /// `(listed path, found path)` pairs.
+    moved: Vec<(String, String)>,
+    new: Vec<String>,
+    missing: Vec<String>,
+}
+
+impl Audit {
+    fn passed(&self) -> bool {
+        self.changed.is_empty()
+            && self.moved.is_empty()
+            && self.new.is_empty()
+            && self.missing.is_empty()
+    }
+}
+
+/// Sorts `found` into the audit categories. Listed paths in `unreadable`
+/// were found but couldn't be hashed, so they don't count as missing.
+fn audit(listed: &[Record], found: &[Record], unreadable: &HashSet<String>) -> Audit {
+    let listed_by_path: HashMap<&str, &Record> = listed
+        .iter()
+        .map(|record| (record.path.as_str(), record))
+        .collect();
+    let found_paths: HashSet<&str> = found.iter().map(|record| record.path.as_str()).collect();
+    let present = |path: &str| found_paths.contains(path) || unreadable.contains(path);
+
+    // Listed files that are gone, by contents, in manifest order.
+    let mut gone: HashMap<(u64, TigerDigest), Vec<&str>> = HashMap::new();
+    for record in listed.iter().rev() {
+        if !present(&record.path) {
+            gone.entry((record.size, record.digest))
+                .or_default()
+                .push(&record.path);
+        }
+    }
+
+    let mut audit = Audit::default();
+    let mut moved_from = HashSet::new();
+    for record in found {
+        match listed_by_path.get(record.path.as_str()) {
+            Some(listed) if listed.size == record.size && listed.digest == record.digest => {
+                audit.matched.push(record.path.clone())
+            }
+            Some(_) => audit.changed.push(record.path.clone()),
+            None => match gone
+                .get_mut(&(record.size, record.digest))
+                .and_then(Vec::pop)
+            {
+                Some(from) => {
+                    moved_from.insert(from);
+                    audit.moved.push((from.to_string(), record.path.clone()));
+                }
+                None => audit.new.push(record.path.clone()),
+            },
+        }
+    }
+    audit.missing = listed
+        .iter()
+        .map(|record| record.path.as_str())
+        .filter(|&path| !present(path) && !moved_from.contains(path))
+        .map(str::to_string)
+        .collect();
+    audit
+}
+
+fn write_audit(out: &mut impl Write, audit: &Audit, verbose: bool) -> io::Result<()> {
+    if verbose {
+        for path in &audit.matched {
+            writeln!(out, "matched: {}", path)?;
+        }
+    }
+    for path in &audit.changed {
+        writeln!(out, "changed: {}", path)?;
+    }
+    for (from, to) in &audit.moved {
+        writeln!(out, "moved: {} -> {}", from, to)?;
+    }
+    for path in &audit.new {
+        writeln!(out, "new: {}", path)?;
+    }
+    for path in &audit.missing {
+        writeln!(out, "missing: {}", path)?;
+    }
+    let result = if audit.passed() { "passed" } else { "failed" };
+    writeln!(out, "tigerdeep: audit {}", result)?;
+    writeln!(out, "        Files matched: {}", audit.matched.len())?;
+    writeln!(out, "        Files changed: {}", audit.changed.len())?;
+    writeln!(out, "          Files moved: {}", audit.moved.len())?;
+    writeln!(out, "      New files found: {}", audit.new.len())?;
+    writeln!(out, "Known files not found: {}", audit.missing.len())
+}
+
+fn write_audit_json(out: &mut impl Write, audit: &Audit) -> io::Result<()> {
+    let list = |paths: &[String]| {
+        let paths: Vec<String> = paths.iter().map(|path| json_string(path)).collect();
+        format!("[{}]", paths.join(", "))
+    };
+    let moved: Vec<String> = audit
+        .moved
+        .iter()
+        .map(|(from, to)| {
+            format!(
+                "{{\"from\": {}, \"to\": {}}}",
+                json_string(from),
+                json_string(to)
+            )
+        })
+        .collect();
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"passed\": {},", audit.passed())?;
+    writeln!(out, "  \"matched\": {},", list(&audit.matched))?;
+    writeln!(out, "  \"changed\": {},", list(&audit.changed))?;
+    writeln!(out, "  \"moved\": [{}],", moved.join(", "))?;
+    writeln!(out, "  \"new\": {},", list(&audit.new))?;
+    writeln!(out, "  \"missing\": {}", list(&audit.missing))?;
+    writeln!(out, "}}")
+}
+
+/// Whether a listed path lies under one of the walk roots at a place the
+/// exclude patterns leave out.
+fn listed_but_excluded(path: &str, options: &Options) -> bool {
+    options.paths.iter().any(|root| {
+        let prefix = format!("{}/", root.trim_end_matches('/'));
+        let Some(relative) = path.strip_prefix(&prefix) else {
+            return false;
+        };
+        // Excluding a directory excludes everything below it.
+        relative
+            .match_indices('/')
+            .map(|(end, _)| &relative[..end])
+            .chain([relative])
+            .any(|prefix| is_excluded(prefix, &options.walk.excludes))
+    })
+}
+
+/// Prints the tree digest of every path. Returns whether all could be
+/// hashed.
+fn print_tree_digests(options: &Options) -> io::Result<bool> {
+    let mut stdout = io::stdout().lock();
+    let mut ok = true;
+    run_parallel(
+        &options.paths,
+        options.jobs,
+        |path| digest_dir(path),
+        |path, result| {
+            match result {
+                Ok(digest) => {
+                    let (prefix, path) = escape(path);
+                    writeln!(stdout, "{}{}  {}", prefix, to_hex(&digest), path)?;
+                }
+                Err(e) => {
+                    eprintln!("tigerdeep: {}", e);
+                    ok = false;
+                }
+            }
+            Ok::<_, io::Error>(())
+        },
+    )?;
+    Ok(ok)
+}
+
+/// Writes one line for a watch event.
+fn write_change(
+    out: &mut impl Write,
+    path: &str,
+    old: Option<String>,
+    new: Option<String>,
+    json: bool,
+) -> io::Result<()> {
+    let event = match (&old, &new) {
+        (None, _) => "new",
+        (_, None) => "missing",
+        _ => "changed",
+    };
+    if json {
+        let digest =
+            |digest: Option<String>| digest.map_or("null".to_string(), |d| json_string(&d));
+        writeln!(
+            out,
+            "{{\"event\": \"{}\", \"path\": {}, \"old\": {}, \"new\": {}}}",
+            event,
+            json_string(path),
+            digest(old),
+            digest(new)
+        )
+    } else {
+        let digest = |digest: Option<String>| digest.unwrap_or_else(|| "-".to_string());
+        let (prefix, path) = escape(path);
+        writeln!(
+            out,
+            "{}{} {} {}  {}",
+            prefix,
+            event,
+            digest(old),
+            digest(new),
+            path
+        )
+    }
+}
+
+/// Watches the single path and prints changes until interrupted or an
+/// error stops the watch.
+#[cfg(target_os = "linux")]
+fn watch(options: &Options) -> io::Result<bool> {
+    use tiger_hash::Watcher;
+
+    let (kind, format): (_, fn(&TigerDigest) -> String) = if options.tth {
+        (DigestKind::Tree, TigerDigest::to_base32)
+    } else {
+        (DigestKind::Tiger, to_hex)
+    };
+    let on_error = |path: &Path, e: io::Error| eprintln!("tigerdeep: {}: {}", path.display(), e);
+    let mut watcher = match Watcher::new(&options.paths[0], kind, on_error) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("tigerdeep: {}", e);
+            return Ok(false);
+        }
+    };
+    eprintln!(
+        "tigerdeep: watching {} files under {}",
+        watcher.files().count(),
+        options.paths[0]
+    );
+    loop {
+        let changes = watcher.next_changes(None, on_error)?;
+        let mut stdout = io::stdout().lock();
+        for change in changes {
+            write_change(
+                &mut stdout,
+                &change.path.display().to_string(),
+                change.old.as_ref().map(format),
+                change.new.as_ref().map(format),
+                options.json,
+            )?;
+        }
+        stdout.flush()?;
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_: &Options) -> io::Result<bool> {
+    eprintln!("tigerdeep: --watch is only supported on Linux");
+    Ok(false)
+}
+
+fn run(options: &Options, invocation: &str) -> io::Result<bool> {
+    if options.digest {
+        return print_tree_digests(options);
+    }
+    if options.watch {
+        return watch(options);
+    }
+    let listed = match &options.audit {
+        Some(manifest) => {
+            let records = File::open(manifest)
+                .map_err(|e| e.to_string())
+                .and_then(|file| parse_manifest(BufReader::new(file)));
+            match records {
+                Ok(records) => Some(records),
+                Err(e) => {
+                    eprintln!("tigerdeep: {}: {}", manifest, e);
+                    return Ok(false);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let cache = match &options.cache {
+        Some(spec) => match Cache::open(spec, "tigerdeep") {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("tigerdeep: {}", e);
+                return Ok(false);
+            }
+        },
+        None => None,
+    };
+    let (found, unreadable) = collect(options, cache.as_ref());
+    if let Some(cache) = cache {
+        cache.flush();
+    }
+    let mut stdout = io::stdout().lock();
+    let Some(mut listed) = listed else {
+        let written = if options.json {
+            write_manifest_json(&mut stdout, &found)?;
+            true
+        } else {
+            write_manifest(&mut stdout, &found, invocation)?
+        };
+        return Ok(written && unreadable.is_empty());
+    };
+
+    listed.retain(|record| !listed_but_excluded(&record.path, options));
+    let audit = audit(&listed, &found, &unreadable);
+    if options.json {
+        write_audit_json(&mut stdout, &audit)?;
+    } else {
+        write_audit(&mut stdout, &audit, options.verbose)?;
+    }
+    Ok(audit.passed() && unreadable.is_empty())
+}
+
+fn main() -> ExitCode {
+    let invocation = std::env::args().collect::<Vec<_>>().join(" ");
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!(
+                "tigerdeep: {}\nTry 'tigerdeep --help' for more information.",
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options, &invocation) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // Most likely a closed pipe on stdout.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("tigerdeep: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str, contents: &[u8]) -> Record {
+        Record {
+            size: contents.len() as u64,
+            digest: tiger_hash::digest(contents),
+            path: path.to_string(),
+        }
+    }
+
+    fn strings(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let records = [record("dir/a", b"abc"), record("dir/b, with comma", b"")];
+        let mut manifest = Vec::new();
+        assert!(write_manifest(&mut manifest, &records, "tigerdeep dir").unwrap());
+        let text = String::from_utf8(manifest.clone()).unwrap();
+        assert!(text.starts_with("%%%% HASHDEEP-1.0\n%%%% size,tiger,filename\n"));
+        assert!(text.contains("\n3,2aab1484e8c158f2bfb8c5ff41b57a525129131c957b5f93,dir/a\n"));
+        assert_eq!(parse_manifest(&manifest[..]).unwrap(), records);
+
+        let mut manifest = Vec::new();
+        let bad = [record("new\nline", b"x")];
+        assert!(!write_manifest(&mut manifest, &bad, "").unwrap());
+        assert!(parse_manifest(&manifest[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_hashdeep_manifest() {
+        let manifest = "\
+%%%% HASHDEEP-1.0
+%%%% size,md5,tiger,filename
+## Invoked from: /tmp
+## $ hashdeep -c md5,tiger -r x
+##
+3,900150983cd24fb0d6963f7d28e17f72,2aab1484e8c158f2bfb8c5ff41b57a525129131c957b5f93,x/abc
+";
+        assert_eq!(
+            parse_manifest(manifest.as_bytes()).unwrap(),
+            [record("x/abc", b"abc")]
+        );
+
+        let errors = [
+            ("3,abc,x\n", "line 1: not a hashdeep manifest"),
+            (
+                "%%%% HASHDEEP-1.0\n%%%% size,md5,filename\n",
+                "line 2: expected",
+            ),
+            (
+                "%%%% HASHDEEP-1.0\n%%%% size,tiger,filename\nx,00,a\n",
+                "line 3: invalid size",
+            ),
+            (
+                "%%%% HASHDEEP-1.0\n%%%% size,tiger,filename\n3,00,a\n",
+                "line 3: expected 48",
+            ),
+            (
+                "%%%% HASHDEEP-1.0\n%%%% size,tiger,filename\n3\n",
+                "line 3: too few",
+            ),
+            ("", "not a hashdeep manifest"),
+        ];
+        for (manifest, expected) in errors {
+            let error = parse_manifest(manifest.as_bytes()).unwrap_err();
+            assert!(error.starts_with(expected), "{:?}: {}", manifest, error);
+        }
+    }
+
+    #[test]
+    fn test_audit() {
+        let listed = [
+            record("same", b"1"),
+            record("edited", b"2"),
+            record("old-name", b"3"),
+            record("deleted", b"4"),
+            record("copied", b"5"),
+            record("locked", b"6"),
+        ];
+        let found = [
+            record("copied", b"5"),
+            record("copy", b"5"),
+            record("edited", b"2!"),
+            record("fresh", b"7"),
+            record("new-name", b"3"),
+            record("same", b"1"),
+        ];
+        let unreadable = HashSet::from(["locked".to_string()]);
+        let audit = audit(&listed, &found, &unreadable);
+        assert_eq!(
+            audit,
+            Audit {
+                matched: strings(&["copied", "same"]),
+                changed: strings(&["edited"]),
+                moved: vec![("old-name".to_string(), "new-name".to_string())],
+                new: strings(&["copy", "fresh"]),
+                missing: strings(&["deleted"]),
+            }
+        );
+        assert!(!audit.passed());
+
+        let audit = super::audit(&listed[..1], &found[5..], &HashSet::new());
+        assert!(audit.passed());
+    }
+
+    #[test]
+    fn test_listed_but_excluded() {
+        let options = match parse_args(strings(&["-x", "*.o", "-x", "tmp/cache", "root/"])) {
+            Ok(Command::Run(options)) => options,
+            _ => unreachable!(),
+        };
+        assert!(listed_but_excluded("root/a.o", &options));
+        assert!(listed_but_excluded("root/src/deep/a.o/inside", &options));
+        assert!(listed_but_excluded("root/tmp/cache/x", &options));
+        assert!(!listed_but_excluded("root/src/tmp/cache/x", &options));
+        assert!(!listed_but_excluded("other/a.o", &options));
+    }
+
+    #[test]
+    fn test_parse_digest_args() {
+        let parse = |args: &[&str]| match parse_args(strings(args)) {
+            Ok(Command::Run(options)) => Ok(options),
+            Ok(Command::Help) => unreachable!(),
+            Err(e) => Err(e),
+        };
+        let options = parse(&["-d", "-j", "2", "a", "b"]).unwrap();
+        assert!(options.digest);
+        assert_eq!(options.paths, ["a", "b"]);
+        for conflicting in [
+            &["-a", "m"][..],
+            &["--cache", "c"],
+            &["--json"],
+            &["-x", "*.o"],
+            &["-L"],
+            &["-v"],
+        ] {
+            assert!(parse(&[&["--digest"], conflicting].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_watch_args() {
+        let parse = |args: &[&str]| match parse_args(strings(args)) {
+            Ok(Command::Run(options)) => Ok(options),
+            Ok(Command::Help) => unreachable!(),
+            Err(e) => Err(e),
+        };
+        let options = parse(&["--watch", "--tth", "--json"]).unwrap();
+        assert!(options.watch && options.tth && options.json);
+        assert_eq!(options.paths, ["."]);
+        assert!(parse(&["--tth", "a"]).is_err());
+        assert!(parse(&["--watch", "a", "b"]).is_err());
+        // `-w` is `--warn` in tigersum and tthsum, so it is not short for `--watch`.
+        assert!(parse(&["-w"]).is_err());
+        for conflicting in [
+            &["-a", "m"][..],
+            &["--cache-xattr"],
+            &["-d"],
+            &["-x", "*.o"],
+            &["-L"],
+            &["-v"],
+        ] {
+            assert!(parse(&[&["--watch"], conflicting].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_change() {
+        let mut out = Vec::new();
+        let (old, new) = (Some("aa".to_string()), Some("bb".to_string()));
+        write_change(&mut out, "dir/a b", old.clone(), new.clone(), false).unwrap();
+        write_change(&mut out, "new\nline", None, new.clone(), false).unwrap();
+        write_change(&mut out, "gone", old.clone(), None, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "changed aa bb  dir/a b\n\
+             \\new - bb  new\\nline\n\
+             {\"event\": \"missing\", \"path\": \"gone\", \"old\": \"aa\", \"new\": null}\n"
+        );
+    }
+}
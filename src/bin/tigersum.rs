@@ -0,0 +1,455 @@
+//! `tigersum`: print or check Tiger checksums, like `sha256sum`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::ExitCode;
+
+use tiger_hash::{Tiger, Variant};
+
+use crate::common::{escape, unescape, CheckCounts};
+
+mod common;
+
+const USAGE: &str = "\
+Usage: tigersum [OPTION]... [FILE]...
+Print or check Tiger checksums. With no FILE, or when FILE is -, read standard input.
+
+  -a, --algo NAME       tiger (default), tiger128, tiger160,
+                        tiger2, tiger2-128 or tiger2-160
+  -c, --check           read checksums from the FILEs and check them
+      --tag             print BSD-style checksums: ALGO (FILE) = HEX
+  -h, --help            print this help
+
+Options for --check:
+      --ignore-missing  don't fail or report status for missing files
+      --quiet           don't print OK for each successfully verified file
+      --status          don't output anything, the exit status shows success
+      --strict          exit non-zero for improperly formatted lines
+  -w, --warn            warn about improperly formatted lines
+
+Exit status is 0 if every file was hashed or verified, 1 otherwise.";
+
+/// A Tiger variant and the number of digest bytes printed for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Algo {
+    variant: Variant,
+    len: usize,
+}
+
+impl Algo {
+    const TIGER: Self = Self {
+        variant: Variant::Tiger,
+        len: 24,
+    };
+
+    fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let (variant, bits) = match name.strip_prefix("tiger2") {
+            Some(bits) => (Variant::Tiger2, bits.strip_prefix('-').unwrap_or(bits)),
+            None => (Variant::Tiger, name.strip_prefix("tiger")?),
+        };
+        let len = match bits {
+            "" | "192" => 24,
+            "160" => 20,
+            "128" => 16,
+            _ => return None,
+        };
+        Some(Self { variant, len })
+    }
+
+    /// The name used with `--algo` and, uppercased, in BSD-style lines.
+    fn name(self) -> String {
+        let base = match self.variant {
+            Variant::Tiger => "tiger",
+            Variant::Tiger2 => "tiger2",
+        };
+        match (self.variant, self.len) {
+            (_, 24) => base.to_string(),
+            (Variant::Tiger, len) => format!("{}{}", base, len * 8),
+            (Variant::Tiger2, len) => format!("{}-{}", base, len * 8),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Options {
+    algo: Option<Algo>,
+    check: bool,
+    tag: bool,
+    ignore_missing: bool,
+    quiet: bool,
+    status: bool,
+    strict: bool,
+    warn: bool,
+    files: Vec<String>,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "-a" | "--algo" => {
+                let name = match value {
+                    Some(name) => name,
+                    None => args.next().ok_or("option --algo needs a value")?,
+                };
+                let algo = Algo::parse(&name).ok_or(format!("unknown algorithm {:?}", name))?;
+                options.algo = Some(algo);
+            }
+            "-c" | "--check" => options.check = true,
+            "--tag" => options.tag = true,
+            "--ignore-missing" => options.ignore_missing = true,
+            "--quiet" => options.quiet = true,
+            "--status" => options.status = true,
+            "--strict" => options.strict = true,
+            "-w" | "--warn" => options.warn = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => {
+                options.files.extend(args);
+                break;
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unrecognized option {:?}", arg));
+            }
+            _ => options.files.push(arg),
+        }
+    }
+    if options.check && options.tag {
+        return Err("--tag cannot be used with --check".to_string());
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// Hashes the file `name`, or standard input for `-`, without loading it
+/// into memory.
+fn hash_file(name: &str, algo: Algo) -> io::Result<Vec<u8>> {
+    let mut hasher = Tiger::with_variant(algo.variant);
+    if name == "-" {
+        hasher.update_reader(io::stdin().lock())?;
+    } else {
+        hasher.update_file(&File::open(name)?)?;
+    }
+    Ok(hasher.finalize().as_bytes()[..algo.len].to_vec())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn format_line(algo: Algo, digest: &[u8], name: &str, tag: bool) -> String {
+    let (prefix, name) = escape(name);
+    if tag {
+        format!(
+            "{}{} ({}) = {}",
+            prefix,
+            algo.name().to_ascii_uppercase(),
+            name,
+            to_hex(digest)
+        )
+    } else {
+        format!("{}{}  {}", prefix, to_hex(digest), name)
+    }
+}
+
+/// One entry of a checksum file.
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    algo: Algo,
+    digest: Vec<u8>,
+    name: String,
+}
+
+/// Parses a `HEX  NAME` or `ALGO (NAME) = HEX` line. Lines in the first form
+/// use `default_algo`.
+fn parse_line(line: &str, default_algo: Algo) -> Option<Entry> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+
+    let tagged = line
+        .split_once(" (")
+        .and_then(|(tag, rest)| Some((Algo::parse(tag)?, rest.rsplit_once(") = ")?)));
+    let (algo, hex, name) = if let Some((algo, (name, hex))) = tagged {
+        (algo, hex, name)
+    } else {
+        let (hex, rest) = line.split_once(' ')?;
+        // A second space marks text mode and `*` binary mode; both hash the
+        // same bytes.
+        let name = rest.strip_prefix([' ', '*'])?;
+        (default_algo, hex, name)
+    };
+
+    // `from_str_radix` would also take a sign, so `+a` must be ruled out here.
+    if hex.len() != algo.len * 2 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) || name.is_empty() {
+        return None;
+    }
+    let digest = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let name = if escaped {
+        unescape(name)?
+    } else {
+        name.to_string()
+    };
+    Some(Entry { algo, digest, name })
+}
+
+/// Prints the checksum of every file. Returns whether all could be read.
+fn print_checksums(options: &Options) -> io::Result<bool> {
+    let algo = options.algo.unwrap_or(Algo::TIGER);
+    let mut stdout = io::stdout().lock();
+    let mut ok = true;
+    for name in &options.files {
+        match hash_file(name, algo) {
+            Ok(digest) => writeln!(stdout, "{}", format_line(algo, &digest, name, options.tag))?,
+            Err(e) => {
+                eprintln!("tigersum: {}: {}", name, e);
+                ok = false;
+            }
+        }
+    }
+    Ok(ok)
+}
+
+/// Verifies the checksums listed in `list`. Returns whether all of them
+/// matched.
+fn check_list(list: &str, options: &Options) -> io::Result<bool> {
+    let reader: Box<dyn BufRead> = if list == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        match File::open(list) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("tigersum: {}: {}", list, e);
+                return Ok(false);
+            }
+        }
+    };
+    let default_algo = options.algo.unwrap_or(Algo::TIGER);
+    let mut stdout = io::stdout().lock();
+    let mut counts = CheckCounts::default();
+    let mut found = false;
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some(entry) = parse_line(line, default_algo) else {
+            counts.improper += 1;
+            if options.warn {
+                eprintln!(
+                    "tigersum: {}: {}: improperly formatted checksum line",
+                    list,
+                    line_no + 1
+                );
+            }
+            continue;
+        };
+        found = true;
+
+        let (prefix, shown) = escape(&entry.name);
+        let result = match hash_file(&entry.name, entry.algo) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && options.ignore_missing => continue,
+            Err(e) => {
+                eprintln!("tigersum: {}: {}", entry.name, e);
+                counts.unreadable += 1;
+                "FAILED open or read"
+            }
+            Ok(digest) if digest == entry.digest => {
+                counts.verified += 1;
+                if options.quiet {
+                    continue;
+                }
+                "OK"
+            }
+            Ok(_) => {
+                counts.failed += 1;
+                "FAILED"
+            }
+        };
+        if !options.status {
+            writeln!(stdout, "{}{}: {}", prefix, shown, result)?;
+        }
+    }
+
+    if !found {
+        eprintln!(
+            "tigersum: {}: no properly formatted checksum lines found",
+            list
+        );
+        return Ok(false);
+    }
+    if !options.status {
+        counts.print_warnings("tigersum");
+    }
+    if options.ignore_missing && counts.verified + counts.failed + counts.unreadable == 0 {
+        eprintln!("tigersum: {}: no file was verified", list);
+        return Ok(false);
+    }
+    Ok(counts.passed(options.strict))
+}
+
+fn run(options: &Options) -> io::Result<bool> {
+    if !options.check {
+        return print_checksums(options);
+    }
+    let mut ok = true;
+    for list in &options.files {
+        ok &= check_list(list, options)?;
+    }
+    Ok(ok)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!(
+                "tigersum: {}\nTry 'tigersum --help' for more information.",
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // Most likely a closed pipe on stdout.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("tigersum: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Options {
+        match parse_args(args.iter().map(|arg| arg.to_string())) {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("failed to parse {:?}", args),
+        }
+    }
+
+    #[test]
+    fn test_algo() {
+        for name in [
+            "tiger",
+            "tiger128",
+            "tiger160",
+            "tiger2",
+            "tiger2-128",
+            "tiger2-160",
+        ] {
+            assert_eq!(Algo::parse(name).unwrap().name(), name);
+        }
+        assert_eq!(Algo::parse("TIGER192"), Some(Algo::TIGER));
+        assert_eq!(Algo::parse("tiger2160").unwrap().name(), "tiger2-160");
+        assert_eq!(Algo::parse("tiger256"), None);
+        assert_eq!(Algo::parse("sha256"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = args(&["-c", "--algo=tiger2", "--quiet", "a", "--", "-b"]);
+        assert!(options.check && options.quiet);
+        assert_eq!(options.algo, Algo::parse("tiger2"));
+        assert_eq!(options.files, ["a", "-b"]);
+        assert_eq!(args(&[]).files, ["-"]);
+        assert_eq!(args(&["-a", "tiger128"]).algo, Algo::parse("tiger128"));
+        assert!(parse_args(["--bogus".to_string()]).is_err());
+        assert!(parse_args(["--tag".to_string(), "-c".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_lines() {
+        let digest = [0xAB; 24];
+        let hex = "ab".repeat(24);
+        assert_eq!(
+            format_line(Algo::TIGER, &digest, "file", false),
+            format!("{}  file", hex)
+        );
+        assert_eq!(
+            format_line(Algo::TIGER, &digest, "file", true),
+            format!("TIGER (file) = {}", hex)
+        );
+        let tiger2_128 = Algo::parse("tiger2-128").unwrap();
+        assert_eq!(
+            format_line(tiger2_128, &digest[..16], "a b", true),
+            format!("TIGER2-128 (a b) = {}", "ab".repeat(16))
+        );
+
+        for (algo, len) in [(Algo::TIGER, 24), (tiger2_128, 16)] {
+            for name in [
+                "file",
+                "with space",
+                "back\\slash",
+                "new\nline",
+                "(paren) = x",
+            ] {
+                for tag in [false, true] {
+                    let line = format_line(algo, &digest[..len], name, tag);
+                    let entry = parse_line(&line, algo).unwrap();
+                    assert_eq!(entry.name, name, "{:?}", line);
+                    assert_eq!(entry.digest, digest[..len]);
+                    assert_eq!(entry.algo, algo);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let hex = "ab".repeat(24);
+        let entry = parse_line(&format!("{} *bin", hex), Algo::TIGER).unwrap();
+        assert_eq!(entry.name, "bin");
+        assert_eq!(
+            parse_line(&format!("{}  x", hex.to_uppercase()), Algo::TIGER)
+                .unwrap()
+                .digest,
+            [0xAB; 24]
+        );
+        // Wrong length for the algorithm.
+        assert_eq!(
+            parse_line(&format!("{}  x", hex), Algo::parse("tiger128").unwrap()),
+            None
+        );
+        assert_eq!(parse_line(&format!("{} x", hex), Algo::TIGER), None);
+        assert_eq!(parse_line(&format!("{}  ", hex), Algo::TIGER), None);
+        assert_eq!(parse_line(&format!("{}  x", &hex[1..]), Algo::TIGER), None);
+        assert_eq!(
+            parse_line(&format!("+a{}  x", &hex[2..]), Algo::TIGER),
+            None
+        );
+        assert_eq!(
+            parse_line(&format!("SHA1 (x) = {}", hex), Algo::TIGER),
+            None
+        );
+        assert_eq!(parse_line("\\zz  bad\\q", Algo::TIGER), None);
+        assert_eq!(parse_line("", Algo::TIGER), None);
+    }
+}
@@ -0,0 +1,463 @@
+//! `tthsum`: print or check Tiger tree hash (TTH) roots.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use tiger_hash::{DigestKind, TigerDigest, TigerTree};
+
+use crate::common::cache::{Cache, CacheSpec};
+use crate::common::{default_jobs, escape, run_parallel, unescape, CheckCounts};
+
+mod common;
+
+const USAGE: &str = "\
+Usage: tthsum [OPTION]... [FILE]...
+Print or check Tiger tree hash (TTH) roots in base32. With no FILE, or when
+FILE is -, read standard input.
+
+  -c, --check       read TTH roots from the FILEs and check them
+  -j, --jobs N      hash up to N files at once (default: one per CPU)
+  -m, --magnet      print magnet links instead of `ROOT  FILE` lines
+      --thex        also write the top of each file's hash tree to FILE.thex
+      --cache CACHE reuse roots recorded in the file CACHE, and record new
+                    ones, for files whose size, times and inode are unchanged
+      --cache-xattr a weaker cache recording roots in an extended attribute
+                    of each file, which can't check the status change time
+                    (Linux only; see below)
+  -h, --help        print this help
+
+Options for --check:
+      --quiet       don't print OK for each successfully verified file
+      --status      don't output anything, the exit status shows success
+      --strict      exit non-zero for improperly formatted lines
+  -w, --warn        warn about improperly formatted lines
+
+A cache trusts file metadata, so a file damaged without its metadata
+changing keeps its recorded root; leave the cache out to catch that. It
+cannot be combined with --thex. --cache-xattr trusts even less of it:
+setting the attribute changes the status change time, so a file rewritten
+and given back its old size and modification time keeps its recorded root
+too. Don't use it for checks that must catch tampering; it warns when used.
+
+A THEX file holds the tree levels from the root down, breadth first, as raw
+24-byte hashes; the lowest level written has at most 1024 nodes.
+
+Exit status is 0 if every file was hashed or verified, 1 otherwise.";
+
+/// Length of a base32 TTH root.
+const ROOT_LEN: usize = 39;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    cache: Option<CacheSpec>,
+    check: bool,
+    magnet: bool,
+    thex: bool,
+    jobs: usize,
+    quiet: bool,
+    status: bool,
+    strict: bool,
+    warn: bool,
+    files: Vec<String>,
+}
+
+enum Command {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        cache: None,
+        check: false,
+        magnet: false,
+        thex: false,
+        jobs: default_jobs(),
+        quiet: false,
+        status: false,
+        strict: false,
+        warn: false,
+        files: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "-j" | "--jobs" => {
+                let jobs = match value {
+                    Some(jobs) => jobs,
+                    None => args.next().ok_or("option --jobs needs a value")?,
+                };
+                options.jobs = match jobs.parse() {
+                    Ok(jobs) if jobs > 0 => jobs,
+                    _ => return Err(format!("invalid number of jobs {:?}", jobs)),
+                };
+            }
+            "--cache" => {
+                let path = match value {
+                    Some(path) => path,
+                    None => args.next().ok_or("option --cache needs a value")?,
+                };
+                options.cache = Some(CacheSpec::File(path));
+            }
+            "--cache-xattr" => options.cache = Some(CacheSpec::Xattr),
+            "-c" | "--check" => options.check = true,
+            "-m" | "--magnet" => options.magnet = true,
+            "--thex" => options.thex = true,
+            "--quiet" => options.quiet = true,
+            "--status" => options.status = true,
+            "--strict" => options.strict = true,
+            "-w" | "--warn" => options.warn = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => {
+                options.files.extend(args);
+                break;
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unrecognized option {:?}", arg));
+            }
+            _ => options.files.push(arg),
+        }
+    }
+    if options.check && (options.magnet || options.thex) {
+        return Err("--magnet and --thex cannot be used with --check".to_string());
+    }
+    if options.thex && options.cache.is_some() {
+        return Err("--thex cannot be combined with a digest cache".to_string());
+    }
+    if options.files.is_empty() {
+        options.files.push("-".to_string());
+    }
+    if options.thex && options.files.iter().any(|name| name == "-") {
+        return Err("--thex needs a file name, not standard input".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// The tree hash of a file and its size.
+struct Hashed {
+    root: TigerDigest,
+    size: u64,
+}
+
+/// Hashes the file `name`, or standard input for `-`, and writes the THEX
+/// file if `thex` is set. Errors are formatted for printing.
+fn hash_file(name: &str, thex: bool, cache: Option<&Cache>) -> Result<Hashed, String> {
+    if let Some(cache) = cache.filter(|_| name != "-") {
+        let (size, root) = cache
+            .digest(Path::new(name), DigestKind::Tree)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        return Ok(Hashed { root, size });
+    }
+
+    let mut tree = TigerTree::new();
+    let size = if name == "-" {
+        tree.update_reader(io::stdin().lock())
+    } else {
+        File::open(name).and_then(|file| tree.update_file(&file))
+    }
+    .map_err(|e| format!("{}: {}", name, e))?;
+
+    let levels = tree.finalize_levels();
+    let root = levels[0][0];
+    if thex {
+        let thex_name = format!("{}.thex", name);
+        let bytes: Vec<u8> = levels
+            .iter()
+            .flatten()
+            .flat_map(|node| *node.as_bytes())
+            .collect();
+        std::fs::write(&thex_name, bytes).map_err(|e| format!("{}: {}", thex_name, e))?;
+    }
+    Ok(Hashed { root, size })
+}
+
+fn format_line(root: &TigerDigest, name: &str) -> String {
+    let (prefix, name) = escape(name);
+    format!("{}{}  {}", prefix, root.to_base32(), name)
+}
+
+/// Formats a magnet link with the TTH root, size and, except for standard
+/// input, the file's base name.
+fn format_magnet(hashed: &Hashed, name: &str) -> String {
+    let mut link = format!(
+        "magnet:?xt=urn:tree:tiger:{}&xl={}",
+        hashed.root.to_base32(),
+        hashed.size
+    );
+    if name != "-" {
+        let base = Path::new(name)
+            .file_name()
+            .map_or(name.into(), |base| base.to_string_lossy());
+        link.push_str("&dn=");
+        link.push_str(&percent_encode(&base));
+    }
+    link
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Parses a `ROOT  NAME` line, accepting the root in either case.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (root, rest) = line.split_once(' ')?;
+    let name = rest.strip_prefix([' ', '*'])?;
+    let root = root.to_ascii_uppercase();
+    let valid = root.len() == ROOT_LEN
+        && root.bytes().all(|c| matches!(c, b'A'..=b'Z' | b'2'..=b'7'))
+        // The last character only carries two bits.
+        && matches!(root.as_bytes()[ROOT_LEN - 1], b'A' | b'I' | b'Q' | b'Y');
+    if !valid || name.is_empty() {
+        return None;
+    }
+    let name = if escaped {
+        unescape(name)?
+    } else {
+        name.to_string()
+    };
+    Some((root, name))
+}
+
+/// Prints the root or magnet link of every file. Returns whether all could
+/// be hashed.
+fn print_roots(options: &Options, cache: Option<&Cache>) -> io::Result<bool> {
+    let mut stdout = io::stdout().lock();
+    let mut ok = true;
+    run_parallel(
+        &options.files,
+        options.jobs,
+        |name| hash_file(name, options.thex, cache),
+        |name, result| {
+            match result {
+                Ok(hashed) if options.magnet => {
+                    writeln!(stdout, "{}", format_magnet(&hashed, name))?
+                }
+                Ok(hashed) => writeln!(stdout, "{}", format_line(&hashed.root, name))?,
+                Err(e) => {
+                    eprintln!("tthsum: {}", e);
+                    ok = false;
+                }
+            }
+            Ok::<_, io::Error>(())
+        },
+    )?;
+    Ok(ok)
+}
+
+/// Verifies the roots listed in `list`. Returns whether all of them matched.
+fn check_list(list: &str, options: &Options, cache: Option<&Cache>) -> io::Result<bool> {
+    let reader: Box<dyn BufRead> = if list == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        match File::open(list) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("tthsum: {}: {}", list, e);
+                return Ok(false);
+            }
+        }
+    };
+
+    let mut counts = CheckCounts::default();
+    let mut entries = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Some(entry) => entries.push(entry),
+            None => {
+                counts.improper += 1;
+                if options.warn {
+                    eprintln!(
+                        "tthsum: {}: {}: improperly formatted TTH line",
+                        list,
+                        line_no + 1
+                    );
+                }
+            }
+        }
+    }
+    if entries.is_empty() {
+        eprintln!("tthsum: {}: no properly formatted TTH lines found", list);
+        return Ok(false);
+    }
+
+    let mut stdout = io::stdout().lock();
+    run_parallel(
+        &entries,
+        options.jobs,
+        |(_, name)| hash_file(name, false, cache),
+        |(expected, name), result| {
+            let status = match result {
+                Ok(hashed) if hashed.root.to_base32() == *expected => {
+                    counts.verified += 1;
+                    if options.quiet {
+                        return Ok(());
+                    }
+                    "OK"
+                }
+                Ok(_) => {
+                    counts.failed += 1;
+                    "FAILED"
+                }
+                Err(e) => {
+                    eprintln!("tthsum: {}", e);
+                    counts.unreadable += 1;
+                    "FAILED open or read"
+                }
+            };
+            if !options.status {
+                let (prefix, name) = escape(name);
+                writeln!(stdout, "{}{}: {}", prefix, name, status)?;
+            }
+            Ok::<_, io::Error>(())
+        },
+    )?;
+
+    if !options.status {
+        counts.print_warnings("tthsum");
+    }
+    Ok(counts.passed(options.strict))
+}
+
+fn run(options: &Options) -> io::Result<bool> {
+    let cache = match &options.cache {
+        Some(spec) => match Cache::open(spec, "tthsum") {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("tthsum: {}", e);
+                return Ok(false);
+            }
+        },
+        None => None,
+    };
+    let result = if options.check {
+        options.files.iter().try_fold(true, |ok, list| {
+            Ok(check_list(list, options, cache.as_ref())? && ok)
+        })
+    } else {
+        print_roots(options, cache.as_ref())
+    };
+    if let Some(cache) = cache {
+        cache.flush();
+    }
+    result
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("tthsum: {}\nTry 'tthsum --help' for more information.", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        // Most likely a closed pipe on stdout.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("tthsum: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY_ROOT: &str = "LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ";
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Command::Run(options) => Ok(options),
+            Command::Help => panic!("unexpected help for {:?}", args),
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = args(&["-j", "3", "--magnet", "a", "--", "-b"]).unwrap();
+        assert_eq!((options.jobs, options.magnet), (3, true));
+        assert_eq!(options.files, ["a", "-b"]);
+        assert_eq!(args(&["--jobs=2"]).unwrap().files, ["-"]);
+        assert!(args(&["-j", "0"]).is_err());
+        assert!(args(&["--thex"]).is_err());
+        assert!(args(&["-c", "--magnet", "list"]).is_err());
+        assert_eq!(
+            args(&["--cache=c", "a"]).unwrap().cache,
+            Some(CacheSpec::File("c".to_string()))
+        );
+        assert!(args(&["--cache-xattr", "--thex", "a"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_lines() {
+        let root = tiger_hash::tree_hash(b"");
+        assert_eq!(root.to_base32(), EMPTY_ROOT);
+        assert_eq!(format_line(&root, "a b"), format!("{}  a b", EMPTY_ROOT));
+        for name in ["a b", "back\\slash", "new\nline"] {
+            let line = format_line(&root, name);
+            assert_eq!(
+                parse_line(&line),
+                Some((EMPTY_ROOT.to_string(), name.to_string()))
+            );
+        }
+        let lower = format!("{} *bin", EMPTY_ROOT.to_lowercase());
+        assert_eq!(
+            parse_line(&lower),
+            Some((EMPTY_ROOT.to_string(), "bin".to_string()))
+        );
+        assert_eq!(parse_line(&format!("{} x", EMPTY_ROOT)), None);
+        assert_eq!(parse_line(&format!("{}  x", &EMPTY_ROOT[1..])), None);
+        assert_eq!(parse_line(&format!("{}1  x", &EMPTY_ROOT[1..])), None);
+        assert_eq!(parse_line(&format!("{}B  x", &EMPTY_ROOT[..38])), None);
+    }
+
+    #[test]
+    fn test_magnet() {
+        let hashed = Hashed {
+            root: tiger_hash::tree_hash(b""),
+            size: 0,
+        };
+        assert_eq!(
+            format_magnet(&hashed, "dir/My File (1).txt"),
+            format!(
+                "magnet:?xt=urn:tree:tiger:{}&xl=0&dn=My%20File%20%281%29.txt",
+                EMPTY_ROOT
+            )
+        );
+        assert_eq!(
+            format_magnet(&hashed, "-"),
+            format!("magnet:?xt=urn:tree:tiger:{}&xl=0", EMPTY_ROOT)
+        );
+    }
+}
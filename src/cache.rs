@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+
+use crate::shared::file_id;
+use crate::{digest, DigestKind, TigerDigest};
+
+const CACHE_MAGIC: [u8; 4] = *b"TGRC";
+/// Version 2 added the path of each entry.
+const CACHE_VERSION: u8 = 2;
+/// Magic, version and entry count.
+const CACHE_HEADER_LEN: usize = 13;
+/// Key, flags, both digests and the length of the path that follows.
+const CACHE_ENTRY_LEN: usize = 48 + 1 + 48 + 4;
+/// A Tiger digest of everything before it.
+const CACHE_CHECKSUM_LEN: usize = 24;
+
+const HAS_TIGER: u8 = 1;
+const HAS_TREE: u8 = 2;
+
+/// What a file's metadata says about its contents: if any of these change,
+/// the contents may have too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileKey {
+    pub dev: u64,
+    pub ino: u64,
+    pub size: u64,
+    /// Modification time in seconds and nanoseconds since the Unix epoch.
+    pub mtime: (i64, u32),
+    /// Status change time in seconds and nanoseconds since the Unix epoch.
+    pub ctime: (i64, u32),
+}
+
+impl FileKey {
+    /// Takes the key fields from `metadata`. Returns `None` on platforms
+    /// without device and inode numbers.
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Some(Self {
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+                size: metadata.size(),
+                mtime: (metadata.mtime(), metadata.mtime_nsec() as u32),
+                ctime: (metadata.ctime(), metadata.ctime_nsec() as u32),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            None
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 48] {
+        let mut bytes = [0; 48];
+        bytes[..8].copy_from_slice(&self.dev.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.ino.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.size.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.mtime.0.to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.mtime.1.to_le_bytes());
+        bytes[36..44].copy_from_slice(&self.ctime.0.to_le_bytes());
+        bytes[44..].copy_from_slice(&self.ctime.1.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; 48]) -> Self {
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Self {
+            dev: u64_at(0),
+            ino: u64_at(8),
+            size: u64_at(16),
+            mtime: (u64_at(24) as i64, u32_at(32)),
+            ctime: (u64_at(36) as i64, u32_at(44)),
+        }
+    }
+}
+
+/// The digests recorded for a file. Either may be missing if it was never
+/// computed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CachedDigests {
+    /// Tiger digest of the contents.
+    pub tiger: Option<TigerDigest>,
+    /// Tiger tree hash root of the contents.
+    pub tree: Option<TigerDigest>,
+}
+
+impl CachedDigests {
+    /// Returns the digest of the given kind, if recorded.
+    pub fn get(&self, kind: DigestKind) -> Option<TigerDigest> {
+        match kind {
+            DigestKind::Tiger => self.tiger,
+            DigestKind::Tree => self.tree,
+        }
+    }
+
+    /// Records the digest of the given kind.
+    pub fn set(&mut self, kind: DigestKind, digest: TigerDigest) {
+        match kind {
+            DigestKind::Tiger => self.tiger = Some(digest),
+            DigestKind::Tree => self.tree = Some(digest),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 49] {
+        let mut bytes = [0; 49];
+        if let Some(tiger) = self.tiger {
+            bytes[0] |= HAS_TIGER;
+            bytes[1..25].copy_from_slice(tiger.as_bytes());
+        }
+        if let Some(tree) = self.tree {
+            bytes[0] |= HAS_TREE;
+            bytes[25..].copy_from_slice(tree.as_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; 49]) -> Self {
+        let digest_at = |at: usize| TigerDigest::from_bytes(bytes[at..at + 24].try_into().unwrap());
+        Self {
+            tiger: (bytes[0] & HAS_TIGER != 0).then(|| digest_at(1)),
+            tree: (bytes[0] & HAS_TREE != 0).then(|| digest_at(25)),
+        }
+    }
+}
+
+/// A store for the digests of files, looked up by [`FileKey`].
+///
+/// An entry only counts while the file's key is the one it was recorded
+/// under, so changing a file invalidates its entry. The key comes from
+/// metadata, though: a cache can't notice contents that change behind the
+/// file system's back, such as on a failing disk. [`CacheFile`] checks the
+/// whole key; `XattrCache` (Linux only) can't check the ctime and is
+/// weaker.
+pub trait DigestCache {
+    /// Returns the digests recorded for the file at `path` if they were
+    /// recorded under `key`.
+    fn get(&self, path: &Path, key: &FileKey) -> Option<CachedDigests>;
+
+    /// Records `digests` for the file at `path` under `key`, replacing what
+    /// was recorded before.
+    fn put(&mut self, path: &Path, key: &FileKey, digests: CachedDigests) -> io::Result<()>;
+
+    /// Writes out changes that are not stored yet.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [`DigestCache`] kept in one compact file.
+///
+/// Entries are found by device and inode number, so a new file that reuses
+/// an inode replaces the entry of the old one. Each entry also records the
+/// absolute path it was stored for, and [`flush`](DigestCache::flush) drops
+/// entries whose path no longer leads to the same inode, so the file only
+/// holds files that still exist; a renamed file is hashed again on the next
+/// run. The file is read by [`open`](Self::open) and only written by
+/// `flush`, which syncs it to disk and then replaces the old one
+/// atomically. It holds a checksum, so a damaged cache file is rejected
+/// rather than trusted; one written by an older version is started afresh.
+#[derive(Clone, Debug)]
+pub struct CacheFile {
+    path: PathBuf,
+    entries: HashMap<(u64, u64), CacheEntry>,
+    dirty: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CacheEntry {
+    key: FileKey,
+    digests: CachedDigests,
+    path: PathBuf,
+}
+
+impl CacheFile {
+    /// Reads the cache file at `path`, or starts an empty cache if there is
+    /// none yet.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = if bytes.is_empty() || is_older_version(&bytes) {
+            HashMap::new()
+        } else {
+            parse_cache(&bytes).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "not a valid digest cache file")
+            })?
+        };
+        Ok(Self {
+            path,
+            entries,
+            dirty: false,
+        })
+    }
+
+    /// Number of files with recorded digests.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no digests are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drops the entries whose path is gone or now leads to another file.
+    fn prune(&mut self) {
+        self.entries.retain(|_, entry| {
+            let id = fs::metadata(&entry.path)
+                .ok()
+                .and_then(|metadata| file_id(&metadata));
+            id == Some((entry.key.dev, entry.key.ino))
+        });
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        // A stable order keeps unchanged caches byte for byte the same.
+        entries.sort_unstable_by_key(|entry| (entry.key.dev, entry.key.ino));
+
+        let mut bytes = Vec::with_capacity(
+            CACHE_HEADER_LEN + entries.len() * CACHE_ENTRY_LEN + CACHE_CHECKSUM_LEN,
+        );
+        bytes.extend_from_slice(&CACHE_MAGIC);
+        bytes.push(CACHE_VERSION);
+        bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for entry in entries {
+            let path = entry.path.as_os_str().as_encoded_bytes();
+            bytes.extend_from_slice(&entry.key.to_bytes());
+            bytes.extend_from_slice(&entry.digests.to_bytes());
+            bytes.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(path);
+        }
+        let checksum = digest(&bytes);
+        bytes.extend_from_slice(checksum.as_bytes());
+        bytes
+    }
+}
+
+impl DigestCache for CacheFile {
+    fn get(&self, _: &Path, key: &FileKey) -> Option<CachedDigests> {
+        match self.entries.get(&(key.dev, key.ino)) {
+            Some(entry) if entry.key == *key => Some(entry.digests),
+            _ => None,
+        }
+    }
+
+    fn put(&mut self, path: &Path, key: &FileKey, digests: CachedDigests) -> io::Result<()> {
+        let entry = CacheEntry {
+            key: *key,
+            digests,
+            path: path::absolute(path)?,
+        };
+        self.entries.insert((key.dev, key.ino), entry);
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.prune();
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(format!(".tmp-{}", std::process::id()));
+        let write = || {
+            let mut file = File::create(&temporary)?;
+            file.write_all(&self.to_bytes())?;
+            // Without this, a crash soon after the rename can leave an empty
+            // cache file behind.
+            file.sync_all()?;
+            fs::rename(&temporary, &self.path)
+        };
+        write().inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })?;
+        #[cfg(unix)]
+        {
+            // Makes the rename itself durable. It has already happened, so a
+            // failure here is no reason to report the flush as failed.
+            let directory = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let _ = File::open(directory).and_then(|directory| directory.sync_all());
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Whether `bytes` are a cache file written by an older version, whose
+/// entries are dropped rather than rejected as invalid.
+fn is_older_version(bytes: &[u8]) -> bool {
+    bytes.len() > 4 && bytes[..4] == CACHE_MAGIC && bytes[4] < CACHE_VERSION
+}
+
+fn parse_cache(bytes: &[u8]) -> Option<HashMap<(u64, u64), CacheEntry>> {
+    let (body, checksum) = bytes.split_at_checked(bytes.len().checked_sub(CACHE_CHECKSUM_LEN)?)?;
+    if body.len() < CACHE_HEADER_LEN
+        || body[..4] != CACHE_MAGIC
+        || body[4] != CACHE_VERSION
+        || digest(body).as_bytes() != checksum
+    {
+        return None;
+    }
+    let count = u64::from_le_bytes(body[5..CACHE_HEADER_LEN].try_into().unwrap());
+    let mut records = &body[CACHE_HEADER_LEN..];
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        let (record, rest) = records.split_first_chunk::<CACHE_ENTRY_LEN>()?;
+        let key = FileKey::from_bytes(record[..48].try_into().unwrap());
+        let digests = CachedDigests::from_bytes(record[48..97].try_into().unwrap());
+        let path_len = u32::from_le_bytes(record[97..].try_into().unwrap());
+        let (path, rest) = rest.split_at_checked(path_len as usize)?;
+        let path = path_from_bytes(path);
+        entries.insert((key.dev, key.ino), CacheEntry { key, digests, path });
+        records = rest;
+    }
+    records.is_empty().then_some(entries)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Entries are only ever stored on Unix, where [`FileKey`] exists.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// A [`DigestCache`] kept in a `user.tiger.cache` extended attribute on
+/// each file.
+///
+/// This backend does not fully keep the [`DigestCache`] contract. Setting
+/// an extended attribute changes the file's ctime, so the ctime can't be
+/// part of the key recorded in the attribute, and
+/// [`get`](DigestCache::get) ignores the ctime of the key it is given. A
+/// file rewritten and then given back its old mtime and size keeps its
+/// entry, which is exactly what an integrity audit has to catch; use
+/// [`CacheFile`] for that. Files that can't carry user attributes, such as
+/// ones the user may not write, make [`put`](DigestCache::put) fail.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XattrCache;
+
+#[cfg(target_os = "linux")]
+impl XattrCache {
+    const NAME: &'static std::ffi::CStr = c"user.tiger.cache";
+    /// Format of the attribute value, independent of [`CACHE_VERSION`].
+    const VERSION: u8 = 1;
+    /// Version and the key without the ctime, then flags and both digests.
+    const VALUE_LEN: usize = 1 + 36 + 49;
+
+    /// Creates the cache.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DigestCache for XattrCache {
+    fn get(&self, path: &Path, key: &FileKey) -> Option<CachedDigests> {
+        let mut value = [0u8; Self::VALUE_LEN];
+        let read = xattr::get(path, Self::NAME, &mut value).ok()?;
+        if read != Self::VALUE_LEN || value[0] != Self::VERSION {
+            return None;
+        }
+        // Everything but the ctime, which `put` moves by setting the
+        // attribute.
+        let key_bytes = key.to_bytes();
+        if value[1..37] != key_bytes[..36] {
+            return None;
+        }
+        Some(CachedDigests::from_bytes(value[37..].try_into().unwrap()))
+    }
+
+    fn put(&mut self, path: &Path, key: &FileKey, digests: CachedDigests) -> io::Result<()> {
+        let mut value = [0u8; Self::VALUE_LEN];
+        value[0] = Self::VERSION;
+        value[1..37].copy_from_slice(&key.to_bytes()[..36]);
+        value[37..].copy_from_slice(&digests.to_bytes());
+        xattr::set(path, Self::NAME, &value)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod xattr {
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    extern "C" {
+        fn getxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+        fn setxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: c_int,
+        ) -> c_int;
+    }
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Reads the attribute `name` of `path` into `value`, returning its
+    /// length.
+    pub(super) fn get(path: &Path, name: &CStr, value: &mut [u8]) -> io::Result<usize> {
+        let path = c_path(path)?;
+        // SAFETY: both strings are NUL-terminated and `value` is writable
+        // for its whole length.
+        let read = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(read as usize)
+    }
+
+    /// Sets the attribute `name` of `path` to `value`.
+    pub(super) fn set(path: &Path, name: &CStr, value: &[u8]) -> io::Result<()> {
+        let path = c_path(path)?;
+        // SAFETY: both strings are NUL-terminated and `value` is readable
+        // for its whole length.
+        let result = unsafe {
+            setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn key(size: u64, mtime: i64) -> FileKey {
+        FileKey {
+            dev: 8,
+            ino: 1234,
+            size,
+            mtime: (mtime, 500),
+            ctime: (mtime + 1, 0),
+        }
+    }
+
+    fn digests(bytes: &[u8]) -> CachedDigests {
+        CachedDigests {
+            tiger: Some(digest(bytes)),
+            tree: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_bytes() {
+        let key = key(u64::MAX, -5);
+        assert_eq!(FileKey::from_bytes(&key.to_bytes()), key);
+        for digests in [
+            CachedDigests::default(),
+            digests(b"abc"),
+            CachedDigests {
+                tiger: None,
+                tree: Some(crate::tree_hash(b"abc")),
+            },
+        ] {
+            assert_eq!(CachedDigests::from_bytes(&digests.to_bytes()), digests);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_cache_file() {
+        let root = temp_dir("cache-file");
+        let path = root.join("cache");
+        let (a, b) = (root.join("a"), root.join("b"));
+        fs::write(&a, b"abc").unwrap();
+        fs::write(&b, b"").unwrap();
+        let key_of = |file: &Path| FileKey::from_metadata(&fs::metadata(file).unwrap()).unwrap();
+        let (key_a, key_b) = (key_of(&a), key_of(&b));
+
+        let mut cache = CacheFile::open(&path).unwrap();
+        assert!(cache.is_empty());
+        cache.put(&a, &key_a, digests(b"abc")).unwrap();
+        cache.put(&b, &key_b, digests(b"")).unwrap();
+        assert_eq!(cache.get(&a, &key_a), Some(digests(b"abc")));
+        cache.flush().unwrap();
+
+        let mut cache = CacheFile::open(&path).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&a, &key_a), Some(digests(b"abc")));
+        // Any change to the key invalidates the entry.
+        assert_eq!(cache.get(&a, &FileKey { size: 4, ..key_a }), None);
+        let moved = FileKey {
+            mtime: (key_a.mtime.0 + 1, 0),
+            ..key_a
+        };
+        assert_eq!(cache.get(&a, &moved), None);
+        let touched = FileKey {
+            ctime: (0, 1),
+            ..key_a
+        };
+        assert_eq!(cache.get(&a, &touched), None);
+        cache.put(&a, &touched, digests(b"xyz")).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&a, &key_a), None);
+        cache.flush().unwrap();
+
+        let entry_len = |file: &Path| 101 + file.as_os_str().len();
+        let mut bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 13 + entry_len(&a) + entry_len(&b) + 24);
+        bytes[20] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let error = CacheFile::open(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // Caches from before entries had paths are started afresh.
+        fs::write(&path, [&CACHE_MAGIC[..], &[1], &bytes[5..]].concat()).unwrap();
+        assert!(CacheFile::open(&path).unwrap().is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_cache_file_prune() {
+        let root = temp_dir("cache-file-prune");
+        let path = root.join("cache");
+        let (kept, removed, replaced) = (
+            root.join("kept"),
+            root.join("removed"),
+            root.join("replaced"),
+        );
+        let mut cache = CacheFile::open(&path).unwrap();
+        for file in [&kept, &removed, &replaced] {
+            fs::write(file, b"abc").unwrap();
+            let key = FileKey::from_metadata(&fs::metadata(file).unwrap()).unwrap();
+            cache.put(file, &key, digests(b"abc")).unwrap();
+        }
+        cache.flush().unwrap();
+        assert_eq!(CacheFile::open(&path).unwrap().len(), 3);
+
+        // A file that is gone, and one whose path now leads to another
+        // inode, lose their entries on the next flush.
+        fs::remove_file(&removed).unwrap();
+        let other = root.join("other");
+        fs::write(&other, b"xyz").unwrap();
+        fs::rename(&other, &replaced).unwrap();
+        let key = FileKey::from_metadata(&fs::metadata(&kept).unwrap()).unwrap();
+        cache.put(&kept, &key, digests(b"abc")).unwrap();
+        cache.flush().unwrap();
+
+        let cache = CacheFile::open(&path).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&kept, &key), Some(digests(b"abc")));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri does not support extended attributes")]
+    fn test_xattr_cache() {
+        const EOPNOTSUPP: i32 = 95;
+
+        let root = temp_dir("cache-xattr");
+        let path = root.join("file");
+        fs::write(&path, b"abc").unwrap();
+        let key = FileKey::from_metadata(&fs::metadata(&path).unwrap()).unwrap();
+        let mut cache = XattrCache::new();
+        assert_eq!(cache.get(&path, &key), None);
+        match cache.put(&path, &key, digests(b"abc")) {
+            Ok(()) => {}
+            // Not every file system takes user attributes.
+            Err(e) if e.raw_os_error() == Some(EOPNOTSUPP) => return,
+            Err(e) => panic!("{}", e),
+        }
+        // The ctime moved when the attribute was set and isn't checked.
+        let now = FileKey::from_metadata(&fs::metadata(&path).unwrap()).unwrap();
+        assert_eq!(cache.get(&path, &now), Some(digests(b"abc")));
+        assert_eq!(cache.get(&path, &FileKey { size: 4, ..now }), None);
+        assert_eq!(cache.get(&path, &FileKey { ino: 1, ..now }), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! Compression function for 32-bit targets.
+//!
+//! Every 64-bit word is kept as a pair of `u32` halves, and each S-box is
+//! split into a table of low halves and a table of high halves. This avoids
+//! the multi-instruction 64-bit additions, subtractions and multiplications a
+//! 32-bit compiler would otherwise generate for each round.
+
+use crate::utils::{passes, SboxTables};
+
+/// The S-boxes t1-t4 split into low and high 32-bit halves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub(crate) struct SplitTables {
+    lo: [[u32; 256]; 4],
+    hi: [[u32; 256]; 4],
+}
+
+impl SplitTables {
+    pub(crate) const fn from_tables(tables: &SboxTables) -> Self {
+        let mut lo = [[0u32; 256]; 4];
+        let mut hi = [[0u32; 256]; 4];
+        let mut i = 0;
+        while i < 1024 {
+            let entry = tables.0[i / 256][i % 256];
+            lo[i / 256][i % 256] = entry as u32;
+            hi[i / 256][i % 256] = (entry >> 32) as u32;
+            i += 1;
+        }
+        Self { lo, hi }
+    }
+
+    #[inline(always)]
+    fn lookup(&self, table: usize, index: u32) -> Word {
+        let index = (index & 0xFF) as usize;
+        Word {
+            lo: self.lo[table][index],
+            hi: self.hi[table][index],
+        }
+    }
+}
+
+/// A 64-bit word as two 32-bit halves.
+#[derive(Clone, Copy)]
+struct Word {
+    lo: u32,
+    hi: u32,
+}
+
+impl Word {
+    #[inline(always)]
+    const fn new(value: u64) -> Self {
+        Self {
+            lo: value as u32,
+            hi: (value >> 32) as u32,
+        }
+    }
+
+    #[inline(always)]
+    const fn get(self) -> u64 {
+        (self.hi as u64) << 32 | self.lo as u64
+    }
+
+    #[inline(always)]
+    fn add(self, other: Self) -> Self {
+        let (lo, carry) = self.lo.overflowing_add(other.lo);
+        Self {
+            lo,
+            hi: self.hi.wrapping_add(other.hi).wrapping_add(carry as u32),
+        }
+    }
+
+    #[inline(always)]
+    fn sub(self, other: Self) -> Self {
+        let (lo, borrow) = self.lo.overflowing_sub(other.lo);
+        Self {
+            lo,
+            hi: self.hi.wrapping_sub(other.hi).wrapping_sub(borrow as u32),
+        }
+    }
+
+    #[inline(always)]
+    fn xor(self, other: Self) -> Self {
+        Self {
+            lo: self.lo ^ other.lo,
+            hi: self.hi ^ other.hi,
+        }
+    }
+
+    #[inline(always)]
+    fn not(self) -> Self {
+        Self {
+            lo: !self.lo,
+            hi: !self.hi,
+        }
+    }
+
+    /// Shifts left by `n`, which must be in `1..32`.
+    #[inline(always)]
+    fn shl(self, n: u32) -> Self {
+        Self {
+            lo: self.lo << n,
+            hi: self.hi << n | self.lo >> (32 - n),
+        }
+    }
+
+    /// Shifts right by `n`, which must be in `1..32`.
+    #[inline(always)]
+    fn shr(self, n: u32) -> Self {
+        Self {
+            lo: self.lo >> n | self.hi << (32 - n),
+            hi: self.hi >> n,
+        }
+    }
+
+    /// Multiplies by one of the pass multipliers with shifts and adds.
+    #[inline(always)]
+    fn mul(self, mul: u64) -> Self {
+        match mul {
+            5 => self.shl(2).add(self),
+            7 => self.shl(3).sub(self),
+            9 => self.shl(3).add(self),
+            _ => unreachable!("unsupported pass multiplier {}", mul),
+        }
+    }
+}
+
+pub(crate) fn compress<const PASSES: usize>(
+    block: &[u64; 8],
+    state: &mut [u64; 3],
+    tables: &SplitTables,
+) {
+    let [mut a, mut b, mut c] = state.map(Word::new);
+    let (aa, bb, cc) = (a, b, c);
+    let mut x = block.map(Word::new);
+    passes!(PASSES, [a, b, c], x, pass, key_schedule, tables);
+
+    *state = [a.xor(aa).get(), b.sub(bb).get(), c.add(cc).get()];
+}
+
+#[inline(always)]
+fn key_schedule(x: &mut [Word; 8]) {
+    x[0] = x[0].sub(x[7].xor(Word::new(0xA5A5A5A5A5A5A5A5)));
+    x[1] = x[1].xor(x[0]);
+    x[2] = x[2].add(x[1]);
+    x[3] = x[3].sub(x[2].xor(x[1].not().shl(19)));
+    x[4] = x[4].xor(x[3]);
+    x[5] = x[5].add(x[4]);
+    x[6] = x[6].sub(x[5].xor(x[4].not().shr(23)));
+    x[7] = x[7].xor(x[6]);
+    x[0] = x[0].add(x[7]);
+    x[1] = x[1].sub(x[0].xor(x[7].not().shl(19)));
+    x[2] = x[2].xor(x[1]);
+    x[3] = x[3].add(x[2]);
+    x[4] = x[4].sub(x[3].xor(x[2].not().shr(23)));
+    x[5] = x[5].xor(x[4]);
+    x[6] = x[6].add(x[5]);
+    x[7] = x[7].sub(x[6].xor(Word::new(0x0123456789ABCDEF)));
+}
+
+#[inline(always)]
+fn pass(a: &mut Word, b: &mut Word, c: &mut Word, x: &[Word; 8], mul: u64, tables: &SplitTables) {
+    round(a, b, c, x[0], mul, tables);
+    round(b, c, a, x[1], mul, tables);
+    round(c, a, b, x[2], mul, tables);
+    round(a, b, c, x[3], mul, tables);
+    round(b, c, a, x[4], mul, tables);
+    round(c, a, b, x[5], mul, tables);
+    round(a, b, c, x[6], mul, tables);
+    round(b, c, a, x[7], mul, tables);
+}
+
+#[inline(always)]
+fn round(a: &mut Word, b: &mut Word, c: &mut Word, x: Word, mul: u64, tables: &SplitTables) {
+    *c = c.xor(x);
+    let (lo, hi) = (c.lo, c.hi);
+    let even = tables
+        .lookup(0, lo)
+        .xor(tables.lookup(1, lo >> 16))
+        .xor(tables.lookup(2, hi))
+        .xor(tables.lookup(3, hi >> 16));
+    let odd = tables
+        .lookup(3, lo >> 8)
+        .xor(tables.lookup(2, lo >> 24))
+        .xor(tables.lookup(1, hi >> 8))
+        .xor(tables.lookup(0, hi >> 24));
+    *a = a.sub(even);
+    *b = b.add(odd).mul(mul);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::check_compress;
+    use crate::utils::{generate_sbox_tables, TIGER_PASSES};
+    use crate::TABLES;
+
+    fn check<const PASSES: usize>(tables: &SboxTables, seed: &mut u64) {
+        let split = SplitTables::from_tables(tables);
+        check_compress::<PASSES>(tables, seed, 2000, |block, state| {
+            compress::<PASSES>(block, state, &split)
+        });
+    }
+
+    #[test]
+    fn test_matches_portable() {
+        let mut seed = 0x9E3779B97F4A7C15;
+        check::<TIGER_PASSES>(&TABLES.wide, &mut seed);
+        check::<4>(&TABLES.wide, &mut seed);
+        check::<5>(&TABLES.wide, &mut seed);
+    }
+
+    #[test]
+    fn test_custom_tables() {
+        let mut seed = 0xD1B54A32D192ED03;
+        check::<TIGER_PASSES>(&generate_sbox_tables(&[0x42; 64], 2), &mut seed);
+    }
+
+    #[test]
+    fn test_word() {
+        let (x, y) = (0xFFFFFFFF00000001u64, 0x00000001FFFFFFFFu64);
+        let (wx, wy) = (Word::new(x), Word::new(y));
+        assert_eq!(wx.add(wy).get(), x.wrapping_add(y));
+        assert_eq!(wy.sub(wx).get(), y.wrapping_sub(x));
+        assert_eq!(wx.shl(19).get(), x << 19);
+        assert_eq!(wx.shr(23).get(), x >> 23);
+        for mul in [5, 7, 9] {
+            assert_eq!(wx.mul(mul).get(), x.wrapping_mul(mul));
+        }
+    }
+}
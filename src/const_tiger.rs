@@ -0,0 +1,99 @@
+use crate::padding::padded_tail;
+use crate::utils::{compress_with_sbox, read_block, write_u64, START_VALUES, TIGER_PASSES};
+use crate::TABLES;
+
+/// Computes the Tiger digest of `bytes` in canonical byte order, usable in
+/// `const` items.
+///
+/// This gives the same digest as [`digest`](crate::digest()), using the same
+/// S-boxes, but is meant for compile-time evaluation. Use
+/// [`tiger!`](crate::tiger!) to hash a string literal. Called at run time it
+/// still uses the portable compression function, so it never takes the
+/// constant-time path of the `ct` feature and shouldn't be given secrets.
+pub const fn tiger(bytes: &[u8]) -> [u8; 24] {
+    let mut state = START_VALUES;
+    let (blocks, rest) = bytes.as_chunks::<64>();
+    compress_blocks(blocks, &mut state);
+    let (tail, tail_len) = padded_tail(rest, bytes.len() as u64, 0x01);
+    compress_blocks(tail.split_at(tail_len).0.as_chunks::<64>().0, &mut state);
+
+    let mut digest = [0u8; 24];
+    let mut i = 0;
+    while i < 24 {
+        digest[i] = write_u64(state[i / 8])[i % 8];
+        i += 1;
+    }
+    digest
+}
+
+const fn compress_blocks(blocks: &[[u8; 64]], state: &mut [u64; 3]) {
+    let mut i = 0;
+    while i < blocks.len() {
+        compress_with_sbox::<TIGER_PASSES>(&read_block(&blocks[i]), state, &TABLES.wide);
+        i += 1;
+    }
+}
+
+/// Computes the Tiger digest of a string at compile time.
+///
+/// `tiger!("abc")` is a `[u8; 24]` constant equal to
+/// [`tiger(b"abc")`](tiger()), in canonical byte order. The argument can be
+/// any constant `&str` expression.
+///
+/// ```
+/// const PROTOCOL_ID: [u8; 24] = tiger_hash::tiger!("example-protocol/1");
+/// assert_eq!(PROTOCOL_ID, *tiger_hash::digest(b"example-protocol/1").as_bytes());
+/// ```
+#[macro_export]
+macro_rules! tiger {
+    ($s:expr) => {{
+        const DIGEST: [u8; 24] = $crate::tiger(::core::primitive::str::as_bytes($s));
+        DIGEST
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash, TigerDigest};
+
+    fn to_hex(digest: [u8; 24]) -> String {
+        TigerDigest::from_bytes(digest).to_string()
+    }
+
+    #[test]
+    fn test_strings() {
+        macro_rules! check {
+            ($($s:expr),* $(,)?) => {
+                $(assert_eq!(to_hex(tiger!($s)), hash($s.as_bytes().to_vec()), "{:?}", $s);)*
+            };
+        }
+        check!(
+            "",
+            "a",
+            "abc",
+            "message digest",
+            "abcdefghijklmnopqrstuvwxyz",
+            "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+            "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+        );
+    }
+
+    #[test]
+    fn test_zeros() {
+        // Every length up to 127, covering all padding cases.
+        const ZEROS: [[u8; 24]; 128] = {
+            let mut digests = [[0u8; 24]; 128];
+            let mut len = 0;
+            while len < 128 {
+                digests[len] = tiger([0; 128].split_at(len).0);
+                len += 1;
+            }
+            digests
+        };
+        for (len, &digest) in ZEROS.iter().enumerate() {
+            assert_eq!(to_hex(digest), hash(vec![0; len]), "length {}", len);
+        }
+    }
+}
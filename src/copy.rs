@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hasher::read_chunks;
+use crate::{DigestOrder, Tiger, TigerDigest};
+
+/// Error returned by [`copy_verified`].
+#[derive(Debug)]
+pub enum CopyError {
+    /// Reading, writing or syncing `path` failed.
+    Io { path: PathBuf, error: io::Error },
+    /// The destination `path` does not read back as what was copied to it.
+    Mismatch {
+        path: PathBuf,
+        source: TigerDigest,
+        destination: TigerDigest,
+    },
+}
+
+impl fmt::Display for CopyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Self::Mismatch {
+                path,
+                source,
+                destination,
+            } => write!(
+                f,
+                "{}: verification failed: source digest {}, destination digest {}",
+                path.display(),
+                source.to_hex(DigestOrder::Canonical).to_ascii_lowercase(),
+                destination
+                    .to_hex(DigestOrder::Canonical)
+                    .to_ascii_lowercase()
+            ),
+        }
+    }
+}
+
+impl Error for CopyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Mismatch { .. } => None,
+        }
+    }
+}
+
+/// Copies the file `from` to `to` and checks that `to` reads back the same,
+/// returning the Tiger digest of the contents.
+///
+/// The source is hashed as it is copied. The destination is then synced to
+/// storage, read back and hashed again; if the digests differ this fails with
+/// [`CopyError::Mismatch`]. Reading back goes through the operating system's
+/// cache, so this catches anything lost or corrupted on the way to the
+/// destination file system but can't prove the bytes on the device itself.
+///
+/// Like [`fs::copy`], this replaces an existing destination and gives it the
+/// permissions of the source. The source must be a regular file, and copying
+/// a file onto itself fails instead of truncating it.
+pub fn copy_verified(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+) -> Result<TigerDigest, CopyError> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let at = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| CopyError::Io { path, error }
+    };
+
+    let source = File::open(from).map_err(at(from))?;
+    let metadata = source.metadata().map_err(at(from))?;
+    if !metadata.is_file() {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, "not a regular file");
+        return Err(at(from)(error));
+    }
+    if is_same_file(from, &metadata, to) {
+        let error = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "source and destination are the same file",
+        );
+        return Err(at(to)(error));
+    }
+    let mut destination = File::create(to).map_err(at(to))?;
+
+    let mut hasher = Tiger::new();
+    // Tell read and write errors apart, which `read_chunks` doesn't.
+    let mut write_failed = false;
+    read_chunks(&source, |chunk| {
+        hasher.update(chunk);
+        destination
+            .write_all(chunk)
+            .inspect_err(|_| write_failed = true)
+    })
+    .map_err(|e| if write_failed { at(to)(e) } else { at(from)(e) })?;
+    destination
+        .set_permissions(metadata.permissions())
+        .and_then(|()| destination.sync_all())
+        .map_err(at(to))?;
+    drop(destination);
+    let copied = hasher.finalize();
+
+    let mut hasher = Tiger::new();
+    File::open(to)
+        .and_then(|file| hasher.update_reader(file))
+        .map_err(at(to))?;
+    let written = hasher.finalize();
+    if written != copied {
+        return Err(CopyError::Mismatch {
+            path: to.to_path_buf(),
+            source: copied,
+            destination: written,
+        });
+    }
+    Ok(copied)
+}
+
+#[cfg(unix)]
+fn is_same_file(_: &Path, from: &fs::Metadata, to: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(to).is_ok_and(|to| (to.dev(), to.ino()) == (from.dev(), from.ino()))
+}
+
+#[cfg(not(unix))]
+fn is_same_file(from: &Path, _: &fs::Metadata, to: &Path) -> bool {
+    match (fs::canonicalize(from), fs::canonicalize(to)) {
+        (Ok(from), Ok(to)) => from == to,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_copy_verified() {
+        let root = temp_dir("copy");
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+        fs::write(root.join("from"), &contents).unwrap();
+        fs::write(root.join("to"), b"old contents, longer than nothing").unwrap();
+
+        let copied = copy_verified(root.join("from"), root.join("to")).unwrap();
+        assert_eq!(copied, digest(&contents));
+        assert_eq!(fs::read(root.join("to")).unwrap(), contents);
+
+        fs::write(root.join("empty"), b"").unwrap();
+        let copied = copy_verified(root.join("empty"), root.join("empty-copy")).unwrap();
+        assert_eq!(copied, digest(b""));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_errors() {
+        let root = temp_dir("errors");
+        fs::write(root.join("file"), b"keep me").unwrap();
+
+        let error = copy_verified(root.join("missing"), root.join("to")).unwrap_err();
+        assert!(matches!(&error, CopyError::Io { path, .. } if *path == root.join("missing")));
+        assert!(!root.join("to").exists());
+
+        let error = copy_verified(root.join("file"), root.join("no/such/dir")).unwrap_err();
+        assert!(matches!(&error, CopyError::Io { path, .. } if *path == root.join("no/such/dir")));
+
+        let error = copy_verified(&root, root.join("to")).unwrap_err();
+        assert!(
+            error.to_string().contains("not a regular file"),
+            "{}",
+            error
+        );
+        assert!(!root.join("to").exists());
+
+        let error = copy_verified(root.join("file"), root.join(".").join("file")).unwrap_err();
+        assert!(error.to_string().contains("same file"), "{}", error);
+        assert_eq!(fs::read(root.join("file")).unwrap(), b"keep me");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_mismatch_message() {
+        let error = CopyError::Mismatch {
+            path: PathBuf::from("dest"),
+            source: digest(b"abc"),
+            destination: digest(b""),
+        };
+        assert_eq!(
+            error.to_string(),
+            "dest: verification failed: \
+             source digest 2aab1484e8c158f2bfb8c5ff41b57a525129131c957b5f93, \
+             destination digest 3293ac630c13f0245f92bbb1766e16167a4e58492dde73f3"
+        );
+    }
+}
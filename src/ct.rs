@@ -0,0 +1,110 @@
+//! Constant-time compression function.
+//!
+//! The fast backends index the S-boxes with bytes of the chaining state, so
+//! which cache lines get loaded depends on the message. Here every lookup
+//! reads all 256 entries of every table and keeps the wanted one with a mask,
+//! so the memory access pattern is the same for every input. This is roughly
+//! two orders of magnitude slower than the table lookups.
+
+use crate::utils::{key_schedule, passes, write_u64, SboxTables};
+
+pub(crate) fn compress<const PASSES: usize>(
+    block: &[u64; 8],
+    state: &mut [u64; 3],
+    tables: &SboxTables,
+) {
+    let [mut a, mut b, mut c] = *state;
+    let mut x = *block;
+    passes!(PASSES, [a, b, c], x, pass, key_schedule, tables);
+
+    *state = [
+        a ^ state[0],
+        b.wrapping_sub(state[1]),
+        c.wrapping_add(state[2]),
+    ];
+}
+
+fn pass(a: &mut u64, b: &mut u64, c: &mut u64, x: &[u64; 8], mul: u64, tables: &SboxTables) {
+    round(a, b, c, x[0], mul, tables);
+    round(b, c, a, x[1], mul, tables);
+    round(c, a, b, x[2], mul, tables);
+    round(a, b, c, x[3], mul, tables);
+    round(b, c, a, x[4], mul, tables);
+    round(c, a, b, x[5], mul, tables);
+    round(a, b, c, x[6], mul, tables);
+    round(b, c, a, x[7], mul, tables);
+}
+
+fn round(a: &mut u64, b: &mut u64, c: &mut u64, x: u64, mul: u64, tables: &SboxTables) {
+    *c ^= x;
+    let [even, odd] = lookup(write_u64(*c), tables);
+    *a = a.wrapping_sub(even);
+    *b = b.wrapping_add(odd).wrapping_mul(mul);
+}
+
+/// Returns `t1[i[0]] ^ t2[i[2]] ^ t3[i[4]] ^ t4[i[6]]` and
+/// `t4[i[1]] ^ t3[i[3]] ^ t2[i[5]] ^ t1[i[7]]`, reading every table entry
+/// once.
+#[inline(always)]
+fn lookup(i: [u8; 8], tables: &SboxTables) -> [u64; 2] {
+    let [t1, t2, t3, t4] = &tables.0;
+    let (mut even, mut odd) = (0, 0);
+    for k in 0..256 {
+        let k = k as u8;
+        even ^= t1[k as usize] & select(i[0], k)
+            ^ t2[k as usize] & select(i[2], k)
+            ^ t3[k as usize] & select(i[4], k)
+            ^ t4[k as usize] & select(i[6], k);
+        odd ^= t4[k as usize] & select(i[1], k)
+            ^ t3[k as usize] & select(i[3], k)
+            ^ t2[k as usize] & select(i[5], k)
+            ^ t1[k as usize] & select(i[7], k);
+    }
+    [even, odd]
+}
+
+/// All ones if `index == k`, zero otherwise, computed without branching.
+#[inline(always)]
+fn select(index: u8, k: u8) -> u64 {
+    // `index ^ k` is 0 only on a match, and only then does subtracting one
+    // borrow into the top bit.
+    let diff = (index ^ k) as u64;
+    0u64.wrapping_sub(diff.wrapping_sub(1) >> 63)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::check_compress;
+    use crate::utils::{generate_sbox_tables, TIGER_PASSES};
+    use crate::TABLES;
+
+    fn check<const PASSES: usize>(tables: &SboxTables, seed: &mut u64) {
+        check_compress::<PASSES>(tables, seed, 200, |block, state| {
+            compress::<PASSES>(block, state, tables)
+        });
+    }
+
+    #[test]
+    fn test_matches_portable() {
+        let mut seed = 0x9E3779B97F4A7C15;
+        check::<TIGER_PASSES>(&TABLES.wide, &mut seed);
+        check::<4>(&TABLES.wide, &mut seed);
+    }
+
+    #[test]
+    fn test_custom_tables() {
+        let mut seed = 0xD1B54A32D192ED03;
+        check::<TIGER_PASSES>(&generate_sbox_tables(&[0x42; 64], 2), &mut seed);
+    }
+
+    #[test]
+    fn test_select() {
+        for index in 0..=255 {
+            for k in 0..=255 {
+                let expected = if index == k { u64::MAX } else { 0 };
+                assert_eq!(select(index, k), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::utils::{read_words, write_words};
+
+/// Byte order used when printing a Tiger digest.
+///
+/// The canonical order serializes every 64-bit chaining word little-endian and
+/// is what the reference implementation, PHP 5.4+ and most current tools print.
+/// Older tools (pre-5.4 PHP, libgcrypt's `GCRY_MD_TIGER`, ...) print every word
+/// big-endian instead, which reverses each group of 8 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DigestOrder {
+    /// Each word little-endian, as produced by [`hash`](crate::hash).
+    #[default]
+    Canonical,
+    /// Each word big-endian.
+    Legacy,
+}
+
+/// A 192-bit Tiger digest.
+///
+/// The digest is stored in canonical byte order; use [`TigerDigest::to_hex`]
+/// and [`TigerDigest::from_hex`] to convert from and to the legacy order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TigerDigest([u8; 24]);
+
+impl TigerDigest {
+    /// Wraps 24 digest bytes given in canonical order.
+    pub const fn from_bytes(bytes: [u8; 24]) -> Self {
+        Self(bytes)
+    }
+
+    /// Builds a digest from the final chaining words `(a, b, c)`.
+    pub fn from_words(words: [u64; 3]) -> Self {
+        let mut bytes = [0u8; 24];
+        write_words(&words, &mut bytes);
+        Self(bytes)
+    }
+
+    /// Wraps 24 digest bytes given in `order`.
+    pub fn from_bytes_in(bytes: [u8; 24], order: DigestOrder) -> Self {
+        Self(reorder(bytes, order))
+    }
+
+    /// Parses a 48-character hex string printed in `order`.
+    pub fn from_hex(hex: &str, order: DigestOrder) -> Result<Self, ParseDigestError> {
+        let hex = hex.as_bytes();
+        if hex.len() != 48 {
+            return Err(ParseDigestError::InvalidLength(hex.len()));
+        }
+        let mut bytes = [0u8; 24];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+            *byte = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+        }
+        Ok(Self::from_bytes_in(bytes, order))
+    }
+
+    /// Returns the digest bytes in canonical order.
+    pub const fn as_bytes(&self) -> &[u8; 24] {
+        &self.0
+    }
+
+    /// Returns the digest bytes in `order`.
+    pub fn to_bytes_in(&self, order: DigestOrder) -> [u8; 24] {
+        reorder(self.0, order)
+    }
+
+    /// Returns the final chaining words `(a, b, c)`.
+    pub fn words(&self) -> [u64; 3] {
+        let mut words = [0u64; 3];
+        read_words(&self.0, &mut words);
+        words
+    }
+
+    /// Formats the digest as uppercase hex in `order`.
+    pub fn to_hex(&self, order: DigestOrder) -> String {
+        self.to_bytes_in(order)
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect()
+    }
+
+    /// Formats the digest as unpadded RFC 4648 base32, the form Tiger tree
+    /// roots are usually shared in.
+    pub fn to_base32(&self) -> String {
+        const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+        let mut base32 = String::with_capacity(39);
+        let (mut bits, mut acc) = (0, 0u16);
+        for &byte in &self.0 {
+            acc = (acc << 8) | byte as u16;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                base32.push(ALPHABET[(acc >> bits) as usize & 31] as char);
+            }
+        }
+        // 192 bits leave 2 over, padded with zero bits.
+        base32.push(ALPHABET[(acc << (5 - bits)) as usize & 31] as char);
+        base32
+    }
+
+    /// Checks a stored hex digest against this one and reports which byte
+    /// order it was written in, or `None` if it matches neither.
+    ///
+    /// The comparison ignores case. If both orders give the same string the
+    /// canonical order is reported.
+    pub fn matches_hex(&self, hex: &str) -> Option<DigestOrder> {
+        [DigestOrder::Canonical, DigestOrder::Legacy]
+            .into_iter()
+            .find(|&order| Self::from_hex(hex, order).is_ok_and(|other| other == *self))
+    }
+}
+
+impl fmt::Display for TigerDigest {
+    /// Formats the digest as uppercase hex in canonical order.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex(DigestOrder::Canonical))
+    }
+}
+
+impl From<[u8; 24]> for TigerDigest {
+    fn from(bytes: [u8; 24]) -> Self {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for TigerDigest {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Converts a hex digest printed in `from` order to `to` order.
+pub fn convert_hex(
+    hex: &str,
+    from: DigestOrder,
+    to: DigestOrder,
+) -> Result<String, ParseDigestError> {
+    Ok(TigerDigest::from_hex(hex, from)?.to_hex(to))
+}
+
+/// Error returned when parsing a hex digest fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseDigestError {
+    /// The string is not 48 characters long.
+    InvalidLength(usize),
+    /// The string contains a character that is not a hex digit.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for ParseDigestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => {
+                write!(f, "expected 48 hex characters, found {}", len)
+            }
+            Self::InvalidCharacter(c) => write!(f, "invalid hex character {:?}", c),
+        }
+    }
+}
+
+impl Error for ParseDigestError {}
+
+/// Swaps between canonical and legacy order. Both directions are the same
+/// operation, so this is its own inverse.
+fn reorder(mut bytes: [u8; 24], order: DigestOrder) -> [u8; 24] {
+    if order == DigestOrder::Legacy {
+        bytes.chunks_exact_mut(8).for_each(<[u8]>::reverse);
+    }
+    bytes
+}
+
+fn hex_value(c: u8) -> Result<u8, ParseDigestError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(ParseDigestError::InvalidCharacter(c as char)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{digest, hash};
+
+    const ABC_CANONICAL: &str = "2AAB1484E8C158F2BFB8C5FF41B57A525129131C957B5F93";
+    const ABC_LEGACY: &str = "F258C1E88414AB2A527AB541FFC5B8BF935F7B951C132951";
+
+    #[test]
+    fn test_to_hex() {
+        let abc = digest(b"abc");
+        assert_eq!(abc.to_string(), hash(b"abc".to_vec()));
+        assert_eq!(abc.to_hex(DigestOrder::Canonical), ABC_CANONICAL);
+        assert_eq!(abc.to_hex(DigestOrder::Legacy), ABC_LEGACY);
+        assert_eq!(
+            abc.words().map(|w| format!("{:016X}", w)).concat(),
+            ABC_LEGACY
+        );
+    }
+
+    #[test]
+    fn test_to_base32() {
+        assert_eq!(
+            digest(b"abc").to_base32(),
+            "FKVRJBHIYFMPFP5YYX7UDNL2KJISSEY4SV5V7EY"
+        );
+        assert_eq!(
+            TigerDigest::from_bytes([0xFF; 24]).to_base32(),
+            "7".repeat(38) + "Y"
+        );
+    }
+
+    #[test]
+    fn test_from_hex() {
+        let abc = digest(b"abc");
+        assert_eq!(
+            TigerDigest::from_hex(ABC_CANONICAL, DigestOrder::Canonical),
+            Ok(abc)
+        );
+        assert_eq!(
+            TigerDigest::from_hex(&ABC_LEGACY.to_lowercase(), DigestOrder::Legacy),
+            Ok(abc)
+        );
+        assert_eq!(
+            TigerDigest::from_hex("2AAB", DigestOrder::Canonical),
+            Err(ParseDigestError::InvalidLength(4))
+        );
+        assert_eq!(
+            TigerDigest::from_hex(&ABC_CANONICAL.replace('F', "G"), DigestOrder::Canonical),
+            Err(ParseDigestError::InvalidCharacter('G'))
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let abc = digest(b"abc");
+        assert_eq!(
+            TigerDigest::from_bytes_in(abc.to_bytes_in(DigestOrder::Legacy), DigestOrder::Legacy),
+            abc
+        );
+        assert_eq!(
+            convert_hex(ABC_CANONICAL, DigestOrder::Canonical, DigestOrder::Legacy),
+            Ok(ABC_LEGACY.to_string())
+        );
+        assert_eq!(
+            convert_hex(ABC_LEGACY, DigestOrder::Legacy, DigestOrder::Canonical),
+            Ok(ABC_CANONICAL.to_string())
+        );
+        assert_eq!(TigerDigest::from_words(abc.words()), abc);
+    }
+
+    #[test]
+    fn test_matches_hex() {
+        let abc = digest(b"abc");
+        assert_eq!(abc.matches_hex(ABC_CANONICAL), Some(DigestOrder::Canonical));
+        assert_eq!(
+            abc.matches_hex(&ABC_LEGACY.to_lowercase()),
+            Some(DigestOrder::Legacy)
+        );
+        assert_eq!(abc.matches_hex(&hash(b"abd".to_vec())), None);
+        assert_eq!(abc.matches_hex("not a digest"), None);
+
+        let palindrome = TigerDigest::from_bytes([0x11; 24]);
+        assert_eq!(
+            palindrome.matches_hex(&"11".repeat(24)),
+            Some(DigestOrder::Canonical)
+        );
+    }
+}
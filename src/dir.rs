@@ -0,0 +1,233 @@
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::Path;
+
+use crate::{Tiger, TigerDigest};
+
+const MAGIC: &[u8] = b"tiger-dir-v1";
+
+const REGULAR: u8 = 1;
+const EXECUTABLE: u8 = 2;
+const SYMLINK: u8 = 3;
+const DIRECTORY: u8 = 4;
+
+/// Computes a Tiger digest of the file, symbolic link or directory tree at
+/// `path` that only depends on its contents.
+///
+/// Names, file contents, the executable bit and symbolic link targets are
+/// covered. Timestamps, ownership, other permission bits and the order in
+/// which the file system lists directory entries are not. Symbolic links are
+/// never followed, not even at `path`. Other file types such as FIFOs,
+/// sockets and devices make this fail with [`io::ErrorKind::Unsupported`].
+///
+/// # Format
+///
+/// The digest is the standard Tiger digest of this serialization, where
+/// `u64(n)` is `n` as 8 little-endian bytes and `bytes(b)` is `u64` of the
+/// length of `b` followed by `b`:
+///
+/// ```text
+/// dir-archive = "tiger-dir-v1" node
+/// node        = 0x01 bytes(contents)          ; regular file
+///             | 0x02 bytes(contents)          ; executable regular file
+///             | 0x03 bytes(target)            ; symbolic link
+///             | 0x04 u64(count) entry*        ; directory with count entries
+/// entry       = bytes(name) node
+/// ```
+///
+/// Directory entries are sorted by the bytes of their names. A file is
+/// executable if its owner execute bit is set; on platforms without
+/// permission bits no file is. Names and link targets are taken as raw bytes
+/// on Unix and in the platform's [`OsStr`](std::ffi::OsStr) encoding
+/// elsewhere.
+///
+/// A file whose length changes while it is read makes this fail rather than
+/// return a digest of a mix of versions.
+pub fn digest_dir(path: impl AsRef<Path>) -> io::Result<TigerDigest> {
+    let mut hasher = Tiger::new();
+    hasher.update(MAGIC);
+    hash_node(&mut hasher, path.as_ref())?;
+    Ok(hasher.finalize())
+}
+
+fn hash_node(hasher: &mut Tiger, path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path).map_err(|e| with_path(path, e))?;
+    let file_type = metadata.file_type();
+    if file_type.is_file() {
+        hash_file(hasher, path, &metadata).map_err(|e| with_path(path, e))
+    } else if file_type.is_symlink() {
+        let target = fs::read_link(path).map_err(|e| with_path(path, e))?;
+        hasher.update(&[SYMLINK]);
+        hash_bytes(hasher, target.as_os_str().as_encoded_bytes());
+        Ok(())
+    } else if file_type.is_dir() {
+        let mut names = fs::read_dir(path)
+            .and_then(|dir| {
+                dir.map(|entry| Ok(entry?.file_name()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .map_err(|e| with_path(path, e))?;
+        names.sort_by(|a, b| a.as_encoded_bytes().cmp(b.as_encoded_bytes()));
+
+        hasher.update(&[DIRECTORY]);
+        hasher.update(&(names.len() as u64).to_le_bytes());
+        for name in names {
+            hash_bytes(hasher, name.as_encoded_bytes());
+            hash_node(hasher, &path.join(name))?;
+        }
+        Ok(())
+    } else {
+        let message = format!("{}: unsupported file type", path.display());
+        Err(io::Error::new(io::ErrorKind::Unsupported, message))
+    }
+}
+
+fn hash_file(hasher: &mut Tiger, path: &Path, metadata: &Metadata) -> io::Result<()> {
+    let kind = if is_executable(metadata) {
+        EXECUTABLE
+    } else {
+        REGULAR
+    };
+    hasher.update(&[kind]);
+    hasher.update(&metadata.len().to_le_bytes());
+    let read = hasher.update_file(&File::open(path)?)?;
+    if read != metadata.len() {
+        return Err(io::Error::other("file changed while it was read"));
+    }
+    Ok(())
+}
+
+fn hash_bytes(hasher: &mut Tiger, bytes: &[u8]) {
+    hasher.update(&(bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Metadata) -> bool {
+    false
+}
+
+/// Adds `path` to an error message, keeping the error kind.
+fn with_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest;
+    use crate::test_util::temp_dir;
+
+    fn bytes(b: &[u8]) -> Vec<u8> {
+        [&(b.len() as u64).to_le_bytes()[..], b].concat()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_format() {
+        let root = temp_dir("format");
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/b"), b"bee").unwrap();
+        fs::write(root.join("a"), b"").unwrap();
+
+        let mut expected = b"tiger-dir-v1".to_vec();
+        expected.push(4);
+        expected.extend(2u64.to_le_bytes());
+        expected.extend(bytes(b"a"));
+        expected.push(1);
+        expected.extend(bytes(b""));
+        expected.extend(bytes(b"sub"));
+        expected.push(4);
+        expected.extend(1u64.to_le_bytes());
+        expected.extend(bytes(b"b"));
+        expected.push(1);
+        expected.extend(bytes(b"bee"));
+        assert_eq!(digest_dir(&root).unwrap(), digest(&expected));
+
+        let file = [b"tiger-dir-v1".to_vec(), vec![1], bytes(b"bee")].concat();
+        assert_eq!(digest_dir(root.join("sub/b")).unwrap(), digest(&file));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_independent_of_creation_order() {
+        let (first, second) = (temp_dir("order1"), temp_dir("order2"));
+        for name in ["x", "b", "a", "B", "ab"] {
+            fs::write(first.join(name), name).unwrap();
+        }
+        for name in ["ab", "B", "a", "x", "b"] {
+            fs::write(second.join(name), name).unwrap();
+        }
+        assert_eq!(digest_dir(&first).unwrap(), digest_dir(&second).unwrap());
+        fs::remove_dir_all(first).unwrap();
+        fs::remove_dir_all(second).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_changes() {
+        let root = temp_dir("changes");
+        fs::write(root.join("file"), b"contents").unwrap();
+        let mut digests = vec![digest_dir(&root).unwrap()];
+
+        fs::write(root.join("file"), b"Contents").unwrap();
+        digests.push(digest_dir(&root).unwrap());
+        fs::rename(root.join("file"), root.join("renamed")).unwrap();
+        digests.push(digest_dir(&root).unwrap());
+        fs::create_dir(root.join("empty")).unwrap();
+        digests.push(digest_dir(&root).unwrap());
+
+        for (i, a) in digests.iter().enumerate() {
+            for b in &digests[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_unix_metadata() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let root = temp_dir("unix");
+        let file = root.join("file");
+        fs::write(&file, b"#!/bin/sh\n").unwrap();
+        let plain = digest_dir(&root).unwrap();
+
+        // Only the owner execute bit counts.
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o611)).unwrap();
+        assert_eq!(digest_dir(&root).unwrap(), plain);
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o700)).unwrap();
+        let executable = digest_dir(&root).unwrap();
+        assert_ne!(executable, plain);
+
+        symlink("file", root.join("link")).unwrap();
+        let link = digest_dir(&root).unwrap();
+        assert_ne!(link, executable);
+        fs::remove_file(root.join("link")).unwrap();
+        symlink("elsewhere", root.join("link")).unwrap();
+        assert_ne!(digest_dir(&root).unwrap(), link);
+
+        let target = [b"tiger-dir-v1".to_vec(), vec![3], bytes(b"elsewhere")].concat();
+        assert_eq!(digest_dir(root.join("link")).unwrap(), digest(&target));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_errors() {
+        let missing = std::env::temp_dir().join("dir-missing-does-not-exist");
+        let error = digest_dir(&missing).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("dir-missing-does-not-exist"));
+    }
+}
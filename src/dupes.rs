@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::shared::{file_id, run_parallel};
+use crate::{DigestKind, Tiger, TigerDigest};
+
+/// Bytes read from each end of a file to tell apart files of the same size
+/// before hashing them completely.
+const PROBE_SIZE: u64 = 4096;
+
+/// Files with the same contents, found by [`find_duplicates`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    /// Size of each file.
+    pub size: u64,
+    /// Digest of each file, of the kind that was asked for.
+    pub digest: TigerDigest,
+    /// Paths to the files, in the order they were given. Hard links to the
+    /// same file are kept next to each other.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Finds the files among `paths` that have the same contents.
+///
+/// Files are compared in stages, each one only reading the files the
+/// previous one could not tell apart: first by size, then by a digest of
+/// their first and last 4 KiB, and finally by the `kind` digest of their
+/// whole contents. Files are only ever reported equal on the digest of their
+/// whole contents. Up to `jobs` files are read at once.
+///
+/// Symbolic links are followed. Empty files are left out, and so are files
+/// that are only duplicated by hard links to themselves; a group contains at
+/// least two different files. Groups come out in the order of their first
+/// path.
+///
+/// A path that can't be read is passed to `on_error` with the error and left
+/// out, as is a file whose size changes while it is read.
+pub fn find_duplicates<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    kind: DigestKind,
+    jobs: usize,
+    mut on_error: impl FnMut(&Path, io::Error),
+) -> Vec<DuplicateGroup> {
+    // Distinct files as (size, indices of all paths leading to the file).
+    let mut files: Vec<(u64, Vec<usize>)> = Vec::new();
+    let mut by_id: HashMap<(u64, u64), usize> = HashMap::new();
+    for (index, metadata) in map_parallel(paths, jobs, |path| fs::metadata(path))
+        .into_iter()
+        .enumerate()
+    {
+        let metadata = match metadata {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => {
+                let error = io::Error::new(io::ErrorKind::InvalidInput, "not a regular file");
+                on_error(paths[index].as_ref(), error);
+                continue;
+            }
+            Err(e) => {
+                on_error(paths[index].as_ref(), e);
+                continue;
+            }
+        };
+        if metadata.len() == 0 {
+            continue;
+        }
+        let id = file_id(&metadata);
+        if let Some(&file) = id.and_then(|id| by_id.get(&id)) {
+            files[file].1.push(index);
+            continue;
+        }
+        if let Some(id) = id {
+            by_id.insert(id, files.len());
+        }
+        files.push((metadata.len(), vec![index]));
+    }
+    let size = |file: usize| files[file].0;
+    let path = |file: usize| paths[files[file].1[0]].as_ref();
+
+    let candidates = split_groups((0..files.len()).map(|file| (size(file), file)));
+
+    // Small files are read completely in the last stage anyway.
+    let to_probe: Vec<usize> = candidates
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|&file| size(file) > 2 * PROBE_SIZE)
+        .collect();
+    let mut probes = HashMap::new();
+    let results = map_parallel(&to_probe, jobs, |&file| probe(path(file), size(file)));
+    for (&file, result) in to_probe.iter().zip(results) {
+        match result {
+            Ok(digest) => {
+                probes.insert(file, digest);
+            }
+            Err(e) => on_error(path(file), e),
+        }
+    }
+    let candidates = split_groups(candidates.into_iter().flatten().filter_map(|file| {
+        if size(file) > 2 * PROBE_SIZE {
+            Some(((size(file), Some(*probes.get(&file)?)), file))
+        } else {
+            Some(((size(file), None), file))
+        }
+    }));
+
+    let to_hash: Vec<usize> = candidates.into_iter().flatten().collect();
+    let mut digests = HashMap::new();
+    let results = map_parallel(&to_hash, jobs, |&file| {
+        hash_file(path(file), size(file), kind)
+    });
+    for (&file, result) in to_hash.iter().zip(results) {
+        match result {
+            Ok(digest) => {
+                digests.insert(file, digest);
+            }
+            Err(e) => on_error(path(file), e),
+        }
+    }
+    let groups = split_groups(
+        to_hash
+            .iter()
+            .filter_map(|&file| Some(((size(file), *digests.get(&file)?), file))),
+    );
+
+    groups
+        .into_iter()
+        .map(|group| DuplicateGroup {
+            size: size(group[0]),
+            digest: digests[&group[0]],
+            paths: group
+                .iter()
+                .flat_map(|&file| &files[file].1)
+                .map(|&index| paths[index].as_ref().to_path_buf())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Groups files by key, keeping only groups of more than one file. Files are
+/// numbered in the order of their first path, and both the files in a group
+/// and the groups come out in that order.
+fn split_groups<K: Hash + Eq>(files: impl IntoIterator<Item = (K, usize)>) -> Vec<Vec<usize>> {
+    let mut groups: HashMap<K, Vec<usize>> = HashMap::new();
+    for (key, file) in files {
+        groups.entry(key).or_default().push(file);
+    }
+    let mut groups: Vec<Vec<usize>> = groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_unstable();
+            group
+        })
+        .collect();
+    groups.sort_unstable_by_key(|group| group[0]);
+    groups
+}
+
+/// Digest of the first and last [`PROBE_SIZE`] bytes of a file of `size`
+/// bytes.
+fn probe(path: &Path, size: u64) -> io::Result<TigerDigest> {
+    let mut file = File::open(path)?;
+    let mut block = [0; PROBE_SIZE as usize];
+    let mut hasher = Tiger::new();
+    for offset in [0, size - PROBE_SIZE] {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut block).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => changed(),
+            _ => e,
+        })?;
+        hasher.update(&block);
+    }
+    Ok(hasher.finalize())
+}
+
+fn hash_file(path: &Path, size: u64, kind: DigestKind) -> io::Result<TigerDigest> {
+    let (read, digest) = kind.digest_file(&File::open(path)?)?;
+    if read != size {
+        return Err(changed());
+    }
+    Ok(digest)
+}
+
+fn changed() -> io::Error {
+    io::Error::other("file changed while it was read")
+}
+
+/// Runs `f` on every item using up to `jobs` threads and returns the results
+/// in the order of `items`.
+fn map_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let mut results = Vec::with_capacity(items.len());
+    let _ = run_parallel(items, jobs, f, |_, result| {
+        results.push(result);
+        Ok::<_, Infallible>(())
+    });
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::{digest, tree_hash};
+
+    fn find(root: &Path, names: &[&str], kind: DigestKind) -> (Vec<DuplicateGroup>, Vec<PathBuf>) {
+        let paths: Vec<PathBuf> = names.iter().map(|name| root.join(name)).collect();
+        let mut errors = Vec::new();
+        let groups = find_duplicates(&paths, kind, 3, |path, _| errors.push(path.to_path_buf()));
+        (groups, errors)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_find_duplicates() {
+        let root = temp_dir("find");
+        let big: Vec<u8> = (0..5 * PROBE_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        // Same size, first and last blocks as `big`, different in between.
+        let mut middle = big.clone();
+        middle[2 * PROBE_SIZE as usize] ^= 1;
+        let mut prefix = big.clone();
+        prefix.push(0);
+        let files: [(&str, &[u8]); 9] = [
+            ("small1", b"small"),
+            ("big1", &big),
+            ("middle", &middle),
+            ("other", b"other"),
+            ("big2", &big),
+            ("small2", b"small"),
+            ("prefix", &prefix),
+            ("empty1", b""),
+            ("empty2", b""),
+        ];
+        for (name, contents) in files {
+            fs::write(root.join(name), contents).unwrap();
+        }
+        let names: Vec<&str> = files.iter().map(|&(name, _)| name).collect();
+
+        let (groups, errors) = find(&root, &names, DigestKind::Tiger);
+        assert!(errors.is_empty());
+        assert_eq!(
+            groups,
+            [
+                DuplicateGroup {
+                    size: 5,
+                    digest: digest(b"small"),
+                    paths: vec![root.join("small1"), root.join("small2")],
+                },
+                DuplicateGroup {
+                    size: big.len() as u64,
+                    digest: digest(&big),
+                    paths: vec![root.join("big1"), root.join("big2")],
+                },
+            ]
+        );
+
+        let (groups, _) = find(&root, &["big2", "middle", "big1"], DigestKind::Tree);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].digest, tree_hash(&big));
+        assert_eq!(groups[0].paths, [root.join("big2"), root.join("big1")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_errors() {
+        let root = temp_dir("errors");
+        fs::write(root.join("a"), b"same").unwrap();
+        fs::write(root.join("b"), b"same").unwrap();
+        let (groups, errors) = find(&root, &["a", "missing", ".", "b"], DigestKind::Tiger);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(errors, [root.join("missing"), root.join(".")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_hard_links() {
+        let root = temp_dir("links");
+        fs::write(root.join("a"), b"same").unwrap();
+        fs::hard_link(root.join("a"), root.join("link")).unwrap();
+
+        // Two names for one file aren't duplicates by themselves.
+        let (groups, _) = find(&root, &["a", "link"], DigestKind::Tiger);
+        assert!(groups.is_empty());
+
+        fs::write(root.join("b"), b"same").unwrap();
+        let (groups, _) = find(&root, &["b", "a", "link"], DigestKind::Tiger);
+        assert_eq!(
+            groups[0].paths,
+            [root.join("b"), root.join("a"), root.join("link")]
+        );
+        let (groups, _) = find(&root, &["a", "b", "link"], DigestKind::Tiger);
+        assert_eq!(
+            groups[0].paths,
+            [root.join("a"), root.join("link"), root.join("b")]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_map_parallel() {
+        let items: Vec<u32> = (0..50).collect();
+        for jobs in [1, 4, 100] {
+            assert_eq!(
+                map_parallel(&items, jobs, |&i| i + 1),
+                (1..51).collect::<Vec<_>>()
+            );
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::padding::PaddedBlocks;
+use crate::sparse::read_sparse;
+use crate::utils::{
+    read_block, read_u64, read_words, write_u64, write_words, START_VALUES, TIGER_PASSES,
+};
+use crate::{compress, digest, TigerDigest, TABLES};
+
+const STATE_MAGIC: [u8; 4] = *b"TGRS";
+const STATE_VERSION: u8 = 1;
+/// Magic, version, variant, chaining words and byte count.
+const STATE_HEADER_LEN: usize = 4 + 1 + 1 + 24 + 8;
+const STATE_CHECKSUM_LEN: usize = 8;
+
+/// The padding rule of a hasher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// Tiger as published, padding with a `0x01` byte.
+    #[default]
+    Tiger,
+    /// Tiger2, identical except that the padding starts with `0x80` as in
+    /// MD4 and the SHA family.
+    Tiger2,
+}
+
+impl Variant {
+    const fn pad(self) -> u8 {
+        match self {
+            Self::Tiger => 0x01,
+            Self::Tiger2 => 0x80,
+        }
+    }
+
+    /// The variant's byte in the saved state format.
+    const fn id(self) -> u8 {
+        match self {
+            Self::Tiger => 1,
+            Self::Tiger2 => 2,
+        }
+    }
+
+    const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::Tiger),
+            2 => Some(Self::Tiger2),
+            _ => None,
+        }
+    }
+}
+
+/// Incremental Tiger hasher for messages that arrive in pieces.
+///
+/// Feeding a message to [`update`](Self::update) in any number of pieces gives
+/// the same digest as [`digest`](crate::digest) of the whole message.
+///
+/// With the `zeroize` feature, the chaining state and the buffered partial
+/// block are wiped on [`reset`](Self::reset) and when the hasher is dropped.
+#[derive(Clone)]
+pub struct Tiger {
+    state: [u64; 3],
+    buffer: [u8; 64],
+    buffer_len: usize,
+    len: u64,
+    variant: Variant,
+}
+
+impl Tiger {
+    /// Creates a hasher for an empty message.
+    pub fn new() -> Self {
+        Self::with_variant(Variant::Tiger)
+    }
+
+    /// Creates a hasher for an empty message that pads according to
+    /// `variant`.
+    pub fn with_variant(variant: Variant) -> Self {
+        Self {
+            state: START_VALUES,
+            buffer: [0; 64],
+            buffer_len: 0,
+            len: 0,
+            variant,
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Appends `bytes` to the message.
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.len = self.len.wrapping_add(bytes.len() as u64);
+
+        if self.buffer_len > 0 {
+            let take = bytes.len().min(64 - self.buffer_len);
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&bytes[..take]);
+            self.buffer_len += take;
+            bytes = &bytes[take..];
+            if self.buffer_len < 64 {
+                return;
+            }
+            compress::<TIGER_PASSES>(&read_block(&self.buffer), &mut self.state, &TABLES);
+            self.buffer_len = 0;
+        }
+
+        let mut blocks = bytes.chunks_exact(64);
+        for block in &mut blocks {
+            compress::<TIGER_PASSES>(
+                &read_block(block.try_into().unwrap()),
+                &mut self.state,
+                &TABLES,
+            );
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
+    }
+
+    /// Appends `len` zero bytes to the message.
+    ///
+    /// Whole blocks of zeros go straight to the compression function as
+    /// zero words, without a buffer to fill or bytes to convert.
+    pub fn update_zeros(&mut self, mut len: u64) {
+        self.len = self.len.wrapping_add(len);
+
+        if self.buffer_len > 0 {
+            let take = len.min(64 - self.buffer_len as u64) as usize;
+            self.buffer[self.buffer_len..self.buffer_len + take].fill(0);
+            self.buffer_len += take;
+            len -= take as u64;
+            if self.buffer_len < 64 {
+                return;
+            }
+            compress::<TIGER_PASSES>(&read_block(&self.buffer), &mut self.state, &TABLES);
+            self.buffer_len = 0;
+        }
+
+        for _ in 0..len / 64 {
+            compress::<TIGER_PASSES>(&[0; 8], &mut self.state, &TABLES);
+        }
+        let rest = (len % 64) as usize;
+        self.buffer[..rest].fill(0);
+        self.buffer_len = rest;
+    }
+
+    /// Appends everything `reader` yields until end of file and returns the
+    /// number of bytes read.
+    pub fn update_reader<R: Read>(&mut self, reader: R) -> io::Result<u64> {
+        read_chunks(reader, |chunk| {
+            self.update(chunk);
+            Ok(())
+        })
+    }
+
+    /// Appends the whole contents of `file` and returns the number of bytes
+    /// appended.
+    ///
+    /// Regular files and block devices are hashed from their start, and on
+    /// Linux the holes of sparse files are hashed as the zeros they read as
+    /// without being read. Anything else, such as a pipe, is read like
+    /// [`update_reader`](Self::update_reader) does.
+    pub fn update_file(&mut self, file: &File) -> io::Result<u64> {
+        read_sparse(file, self)
+    }
+
+    /// Returns the digest of the message.
+    pub fn finalize(mut self) -> TigerDigest {
+        self.finalize_reset()
+    }
+
+    /// Returns the digest of the message and resets the hasher.
+    pub fn finalize_reset(&mut self) -> TigerDigest {
+        let mut state = self.state;
+        let tail = &self.buffer[..self.buffer_len];
+        for block in PaddedBlocks::with_len(tail, self.len, self.variant.pad()) {
+            compress::<TIGER_PASSES>(&block, &mut state, &TABLES);
+        }
+        self.reset();
+        TigerDigest::from_words(state)
+    }
+
+    /// Saves the midstate so hashing can continue later, possibly in another
+    /// process, with [`restore_state`](Self::restore_state).
+    ///
+    /// The format is the magic `TGRS`, a version byte, a variant byte (1 for
+    /// Tiger, 2 for Tiger2), the chaining words and the byte count as
+    /// little-endian `u64`s, the bytes of the partial block, and the first 8
+    /// bytes of the Tiger digest of all that. It contains message bytes, so
+    /// protect it like the message.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATE_HEADER_LEN + 64 + STATE_CHECKSUM_LEN);
+        bytes.extend_from_slice(&STATE_MAGIC);
+        bytes.extend_from_slice(&[STATE_VERSION, self.variant.id()]);
+        let mut words = [0u8; 24];
+        write_words(&self.state, &mut words);
+        bytes.extend_from_slice(&words);
+        bytes.extend_from_slice(&write_u64(self.len));
+        bytes.extend_from_slice(&self.buffer[..self.buffer_len]);
+        let checksum = digest(&bytes);
+        bytes.extend_from_slice(&checksum.as_bytes()[..STATE_CHECKSUM_LEN]);
+        bytes
+    }
+
+    /// Restores a hasher saved with [`save_state`](Self::save_state).
+    pub fn restore_state(bytes: &[u8]) -> Result<Self, RestoreStateError> {
+        if bytes.len() < STATE_HEADER_LEN + STATE_CHECKSUM_LEN {
+            return Err(RestoreStateError::InvalidLength(bytes.len()));
+        }
+        if bytes[..4] != STATE_MAGIC {
+            return Err(RestoreStateError::InvalidMagic);
+        }
+        if bytes[4] != STATE_VERSION {
+            return Err(RestoreStateError::UnsupportedVersion(bytes[4]));
+        }
+        let Some(variant) = Variant::from_id(bytes[5]) else {
+            return Err(RestoreStateError::UnsupportedVariant(bytes[5]));
+        };
+        let len = read_u64(bytes[30..STATE_HEADER_LEN].try_into().unwrap());
+        let buffer_len = (len % 64) as usize;
+        let body_len = STATE_HEADER_LEN + buffer_len;
+        if bytes.len() != body_len + STATE_CHECKSUM_LEN {
+            return Err(RestoreStateError::InvalidLength(bytes.len()));
+        }
+        let (body, checksum) = bytes.split_at(body_len);
+        if digest(body).as_bytes()[..STATE_CHECKSUM_LEN] != *checksum {
+            return Err(RestoreStateError::ChecksumMismatch);
+        }
+
+        let mut hasher = Self::with_variant(variant);
+        read_words(&body[6..30], &mut hasher.state);
+        hasher.len = len;
+        hasher.buffer[..buffer_len].copy_from_slice(&body[STATE_HEADER_LEN..]);
+        hasher.buffer_len = buffer_len;
+        Ok(hasher)
+    }
+
+    /// Discards the message so far. The variant is kept.
+    pub fn reset(&mut self) {
+        #[cfg(feature = "zeroize")]
+        self.buffer.zeroize();
+        self.state = START_VALUES;
+        self.buffer_len = 0;
+        self.len = 0;
+    }
+}
+
+impl Default for Tiger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The state and buffer are derived from the message, which may be secret.
+impl fmt::Debug for Tiger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tiger").finish_non_exhaustive()
+    }
+}
+
+impl io::Write for Tiger {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Error returned when a saved hasher state cannot be restored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreStateError {
+    /// The input is too short, or its length does not match the byte count
+    /// it contains.
+    InvalidLength(usize),
+    /// The input does not start with the saved state magic bytes.
+    InvalidMagic,
+    /// The input was saved in a format version this crate cannot read.
+    UnsupportedVersion(u8),
+    /// The input was saved by a different hasher variant.
+    UnsupportedVariant(u8),
+    /// The checksum does not match, so the input is corrupt.
+    ChecksumMismatch,
+}
+
+impl fmt::Display for RestoreStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => write!(f, "invalid saved state length {}", len),
+            Self::InvalidMagic => write!(f, "not a saved Tiger hasher state"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported saved state version {}", version)
+            }
+            Self::UnsupportedVariant(variant) => {
+                write!(f, "unsupported hasher variant {}", variant)
+            }
+            Self::ChecksumMismatch => write!(f, "saved state checksum mismatch"),
+        }
+    }
+}
+
+impl Error for RestoreStateError {}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Tiger {
+    fn drop(&mut self) {
+        self.state.zeroize();
+        self.buffer.zeroize();
+        self.buffer_len.zeroize();
+        self.len.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for Tiger {}
+
+/// Passes everything `reader` yields until end of file to `f` in chunks of up
+/// to 64 KiB and returns the number of bytes read. Stops at the first error
+/// from `f`.
+pub(crate) fn read_chunks<R: Read>(
+    mut reader: R,
+    mut f: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    let result = loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break Ok(total),
+            Ok(read) => {
+                if let Err(e) = f(&buffer[..read]) {
+                    break Err(e);
+                }
+                total += read as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => break Err(e),
+        }
+    };
+    #[cfg(feature = "zeroize")]
+    buffer.zeroize();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest;
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    #[test]
+    fn test_matches_digest() {
+        for len in [0, 1, 55, 56, 63, 64, 65, 127, 128, 1000] {
+            let message = message(len);
+            for piece in [1, 3, 63, 64, 65, 500] {
+                let mut hasher = Tiger::new();
+                for chunk in message.chunks(piece) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(
+                    hasher.finalize(),
+                    digest(&message),
+                    "length {}, pieces of {}",
+                    len,
+                    piece
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut hasher = Tiger::new();
+        hasher.update(b"secret");
+        hasher.reset();
+        hasher.update(b"abc");
+        assert_eq!(hasher.finalize_reset(), digest(b"abc"));
+        assert_eq!(hasher.finalize_reset(), digest(b""));
+    }
+
+    #[test]
+    fn test_tiger2() {
+        let tiger2 = |message: &[u8]| {
+            let mut hasher = Tiger::with_variant(Variant::Tiger2);
+            hasher.update(message);
+            hasher.finalize().to_string()
+        };
+        assert_eq!(
+            tiger2(b""),
+            "4441BE75F6018773C206C22745374B924AA8313FEF919F41"
+        );
+        assert_eq!(
+            tiger2(b"The quick brown fox jumps over the lazy dog"),
+            "976ABFF8062A2E9DCEA3A1ACE966ED9C19CB85558B4976D8"
+        );
+        assert_ne!(tiger2(b"abc"), digest(b"abc").to_string());
+
+        let mut hasher = Tiger::with_variant(Variant::Tiger2);
+        hasher.update(&message(100));
+        let mut restored = Tiger::restore_state(&hasher.save_state()).unwrap();
+        assert_eq!(restored.variant(), Variant::Tiger2);
+        restored.reset();
+        assert_eq!(restored.variant(), Variant::Tiger2);
+    }
+
+    #[test]
+    fn test_update_reader() {
+        let mut hasher = Tiger::new();
+        let message = message(200_000);
+        assert_eq!(hasher.update_reader(&message[..]).unwrap(), 200_000);
+        assert_eq!(hasher.finalize(), digest(&message));
+    }
+
+    #[test]
+    fn test_update_zeros() {
+        for len in [0, 1, 63, 64, 65, 200, 1000] {
+            for before in [0, 1, 63, 64, 100] {
+                let mut message = message(before);
+                let mut hasher = Tiger::new();
+                hasher.update(&message);
+                hasher.update_zeros(len);
+                hasher.update(b"end");
+                message.resize(before + len as usize, 0);
+                message.extend_from_slice(b"end");
+                assert_eq!(
+                    hasher.finalize(),
+                    digest(&message),
+                    "{} zeros after {} bytes",
+                    len,
+                    before
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let mut hasher = Tiger::new();
+        io::copy(&mut &message(300)[..], &mut hasher).unwrap();
+        assert_eq!(hasher.finalize(), digest(&message(300)));
+    }
+
+    #[test]
+    fn test_resume() {
+        let message = message(1000);
+        for split in [0, 1, 63, 64, 65, 500, 1000] {
+            let mut hasher = Tiger::new();
+            hasher.update(&message[..split]);
+            let saved = hasher.save_state();
+            assert_eq!(saved.len(), 46 + split % 64);
+
+            let mut hasher = Tiger::restore_state(&saved).unwrap();
+            hasher.update(&message[split..]);
+            assert_eq!(hasher.finalize(), digest(&message), "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn test_restore_errors() {
+        let mut hasher = Tiger::new();
+        hasher.update(&message(100));
+        let saved = hasher.save_state();
+
+        for i in 0..saved.len() {
+            let mut corrupt = saved.clone();
+            corrupt[i] ^= 0x10;
+            assert!(Tiger::restore_state(&corrupt).is_err(), "byte {}", i);
+        }
+        let mut corrupt = saved.clone();
+        corrupt[20] ^= 1;
+        assert_eq!(
+            Tiger::restore_state(&corrupt).unwrap_err(),
+            RestoreStateError::ChecksumMismatch
+        );
+        let mut other = saved.clone();
+        other[4] = 2;
+        assert_eq!(
+            Tiger::restore_state(&other).unwrap_err(),
+            RestoreStateError::UnsupportedVersion(2)
+        );
+        other[4] = 1;
+        other[5] = 3;
+        assert_eq!(
+            Tiger::restore_state(&other).unwrap_err(),
+            RestoreStateError::UnsupportedVariant(3)
+        );
+        assert_eq!(
+            Tiger::restore_state(b"TGRS").unwrap_err(),
+            RestoreStateError::InvalidLength(4)
+        );
+        assert_eq!(
+            Tiger::restore_state(&saved[..saved.len() - 1]).unwrap_err(),
+            RestoreStateError::InvalidLength(saved.len() - 1)
+        );
+        assert_eq!(
+            Tiger::restore_state(&[0; 46]).unwrap_err(),
+            RestoreStateError::InvalidMagic
+        );
+    }
+
+    #[test]
+    fn test_debug_hides_contents() {
+        let mut hasher = Tiger::new();
+        hasher.update(b"secret");
+        assert_eq!(format!("{:?}", hasher), "Tiger { .. }");
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_reset_wipes_buffer() {
+        let mut hasher = Tiger::new();
+        hasher.update(b"secret");
+        hasher.reset();
+        assert_eq!(hasher.buffer, [0; 64]);
+        assert_eq!(hasher.state, START_VALUES);
+    }
+}
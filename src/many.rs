@@ -0,0 +1,382 @@
+use crate::padding::PaddedBlocks;
+use crate::utils::{START_VALUES, TIGER_PASSES};
+use crate::{compress, TigerDigest, TABLES};
+
+/// Computes the Tiger digests of many independent messages.
+///
+/// A single Tiger computation is serial, but independent messages can be
+/// interleaved. On x86_64 CPUs with AVX-512 or AVX2 this runs 8 or 4 messages
+/// at once, one per vector lane, using gathers for the S-box lookups. Other
+/// CPUs fall back to hashing the messages one by one. Every digest is the
+/// same as [`digest`](crate::digest) of the corresponding message.
+///
+/// Gathers index the tables with secret data, so with the `ct` feature the
+/// messages are always hashed one by one with the constant-time backend.
+pub fn hash_many(messages: &[&[u8]]) -> Vec<TigerDigest> {
+    #[cfg(all(target_arch = "x86_64", not(feature = "ct")))]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            // SAFETY: AVX-512F support was just checked.
+            return unsafe { x86::hash_many_avx512(messages) };
+        }
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked.
+            return unsafe { x86::hash_many_avx2(messages) };
+        }
+    }
+    hash_many_scalar(messages)
+}
+
+pub(crate) fn hash_many_scalar(messages: &[&[u8]]) -> Vec<TigerDigest> {
+    hash_lanes::<1>(messages, |states, blocks| {
+        compress::<TIGER_PASSES>(&blocks[0], &mut states[0], &TABLES)
+    })
+}
+
+/// Schedules `messages` onto `LANES` lanes and calls `compress` with one block
+/// per lane until every message has been hashed.
+///
+/// A lane picks up the next message as soon as its current one is done, so
+/// messages of different lengths keep all lanes busy. Lanes without work are
+/// still compressed, but their results are ignored.
+fn hash_lanes<const LANES: usize>(
+    messages: &[&[u8]],
+    mut compress: impl FnMut(&mut [[u64; 3]; LANES], &[[u64; 8]; LANES]),
+) -> Vec<TigerDigest> {
+    let mut digests = vec![TigerDigest::from_bytes([0; 24]); messages.len()];
+    let mut pending = messages.iter().enumerate();
+    let mut lanes: [Option<(usize, PaddedBlocks)>; LANES] = std::array::from_fn(|_| None);
+    let mut states = [START_VALUES; LANES];
+    let mut blocks = [[0u64; 8]; LANES];
+
+    loop {
+        let mut active = false;
+        for lane in 0..LANES {
+            loop {
+                if let Some((index, padded)) = &mut lanes[lane] {
+                    if let Some(block) = padded.next() {
+                        blocks[lane] = block;
+                        active = true;
+                        break;
+                    }
+                    digests[*index] = TigerDigest::from_words(states[lane]);
+                }
+                match pending.next() {
+                    Some((index, message)) => {
+                        lanes[lane] = Some((index, PaddedBlocks::new(message)));
+                        states[lane] = START_VALUES;
+                    }
+                    None => {
+                        lanes[lane] = None;
+                        break;
+                    }
+                }
+            }
+        }
+        if !active {
+            return digests;
+        }
+        compress(&mut states, &blocks);
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "ct")))]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::hash_lanes;
+    use crate::utils::{passes, SboxTables, TIGER_PASSES};
+    use crate::{TigerDigest, TABLES};
+
+    /// The vector operations the multi-lane compression function needs. Every
+    /// lane holds one 64-bit word of an independent message.
+    ///
+    /// The methods are only sound to call if the CPU supports the instruction
+    /// set of the implementing type.
+    trait Vector: Copy {
+        const LANES: usize;
+
+        unsafe fn load(words: &[u64]) -> Self;
+        unsafe fn store(self, words: &mut [u64]);
+        unsafe fn splat(word: u64) -> Self;
+        unsafe fn add(self, other: Self) -> Self;
+        unsafe fn sub(self, other: Self) -> Self;
+        unsafe fn xor(self, other: Self) -> Self;
+        unsafe fn and(self, other: Self) -> Self;
+        unsafe fn shl(self, count: i64) -> Self;
+        unsafe fn shr(self, count: i64) -> Self;
+        /// Looks up `table[index]` in every lane. Every index must be < 256.
+        unsafe fn gather(table: &[u64; 256], index: Self) -> Self;
+    }
+
+    impl Vector for __m256i {
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        unsafe fn load(words: &[u64]) -> Self {
+            assert!(words.len() >= Self::LANES);
+            _mm256_loadu_si256(words.as_ptr().cast())
+        }
+        #[inline(always)]
+        unsafe fn store(self, words: &mut [u64]) {
+            assert!(words.len() >= Self::LANES);
+            _mm256_storeu_si256(words.as_mut_ptr().cast(), self)
+        }
+        #[inline(always)]
+        unsafe fn splat(word: u64) -> Self {
+            _mm256_set1_epi64x(word as i64)
+        }
+        #[inline(always)]
+        unsafe fn add(self, other: Self) -> Self {
+            _mm256_add_epi64(self, other)
+        }
+        #[inline(always)]
+        unsafe fn sub(self, other: Self) -> Self {
+            _mm256_sub_epi64(self, other)
+        }
+        #[inline(always)]
+        unsafe fn xor(self, other: Self) -> Self {
+            _mm256_xor_si256(self, other)
+        }
+        #[inline(always)]
+        unsafe fn and(self, other: Self) -> Self {
+            _mm256_and_si256(self, other)
+        }
+        #[inline(always)]
+        unsafe fn shl(self, count: i64) -> Self {
+            _mm256_sll_epi64(self, _mm_cvtsi64_si128(count))
+        }
+        #[inline(always)]
+        unsafe fn shr(self, count: i64) -> Self {
+            _mm256_srl_epi64(self, _mm_cvtsi64_si128(count))
+        }
+        #[inline(always)]
+        unsafe fn gather(table: &[u64; 256], index: Self) -> Self {
+            _mm256_i64gather_epi64::<8>(table.as_ptr().cast(), index)
+        }
+    }
+
+    impl Vector for __m512i {
+        const LANES: usize = 8;
+
+        #[inline(always)]
+        unsafe fn load(words: &[u64]) -> Self {
+            assert!(words.len() >= Self::LANES);
+            _mm512_loadu_si512(words.as_ptr().cast())
+        }
+        #[inline(always)]
+        unsafe fn store(self, words: &mut [u64]) {
+            assert!(words.len() >= Self::LANES);
+            _mm512_storeu_si512(words.as_mut_ptr().cast(), self)
+        }
+        #[inline(always)]
+        unsafe fn splat(word: u64) -> Self {
+            _mm512_set1_epi64(word as i64)
+        }
+        #[inline(always)]
+        unsafe fn add(self, other: Self) -> Self {
+            _mm512_add_epi64(self, other)
+        }
+        #[inline(always)]
+        unsafe fn sub(self, other: Self) -> Self {
+            _mm512_sub_epi64(self, other)
+        }
+        #[inline(always)]
+        unsafe fn xor(self, other: Self) -> Self {
+            _mm512_xor_si512(self, other)
+        }
+        #[inline(always)]
+        unsafe fn and(self, other: Self) -> Self {
+            _mm512_and_si512(self, other)
+        }
+        #[inline(always)]
+        unsafe fn shl(self, count: i64) -> Self {
+            _mm512_sll_epi64(self, _mm_cvtsi64_si128(count))
+        }
+        #[inline(always)]
+        unsafe fn shr(self, count: i64) -> Self {
+            _mm512_srl_epi64(self, _mm_cvtsi64_si128(count))
+        }
+        #[inline(always)]
+        unsafe fn gather(table: &[u64; 256], index: Self) -> Self {
+            _mm512_i64gather_epi64::<8>(index, table.as_ptr().cast())
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(crate) unsafe fn hash_many_avx2(messages: &[&[u8]]) -> Vec<TigerDigest> {
+        hash_lanes::<4>(messages, |states, blocks| {
+            // SAFETY: AVX2 is enabled for this function.
+            unsafe { compress::<__m256i, TIGER_PASSES, 4>(states, blocks, &TABLES.wide) }
+        })
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX-512F.
+    #[target_feature(enable = "avx512f")]
+    pub(crate) unsafe fn hash_many_avx512(messages: &[&[u8]]) -> Vec<TigerDigest> {
+        hash_lanes::<8>(messages, |states, blocks| {
+            // SAFETY: AVX-512F is enabled for this function.
+            unsafe { compress::<__m512i, TIGER_PASSES, 8>(states, blocks, &TABLES.wide) }
+        })
+    }
+
+    /// [`compress_with_sbox`](crate::utils::compress_with_sbox) on `LANES`
+    /// independent states and blocks at once.
+    #[inline(always)]
+    unsafe fn compress<V: Vector, const PASSES: usize, const LANES: usize>(
+        states: &mut [[u64; 3]; LANES],
+        blocks: &[[u64; 8]; LANES],
+        tables: &SboxTables,
+    ) {
+        assert_eq!(LANES, V::LANES);
+
+        let column = |words: &dyn Fn(usize) -> u64| {
+            let lanes: [u64; LANES] = std::array::from_fn(words);
+            V::load(&lanes)
+        };
+        let (mut a, mut b, mut c) = (
+            column(&|lane| states[lane][0]),
+            column(&|lane| states[lane][1]),
+            column(&|lane| states[lane][2]),
+        );
+        let (aa, bb, cc) = (a, b, c);
+        let mut x: [V; 8] = std::array::from_fn(|i| column(&|lane| blocks[lane][i]));
+
+        passes!(PASSES, [a, b, c], x, pass, key_schedule, tables);
+
+        let mut words = [[0u64; LANES]; 3];
+        a.xor(aa).store(&mut words[0]);
+        b.sub(bb).store(&mut words[1]);
+        c.add(cc).store(&mut words[2]);
+        for (lane, state) in states.iter_mut().enumerate() {
+            *state = [words[0][lane], words[1][lane], words[2][lane]];
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn key_schedule<V: Vector>(x: &mut [V; 8]) {
+        let not = |v: V| v.xor(V::splat(u64::MAX));
+        x[0] = x[0].sub(x[7].xor(V::splat(0xA5A5A5A5A5A5A5A5)));
+        x[1] = x[1].xor(x[0]);
+        x[2] = x[2].add(x[1]);
+        x[3] = x[3].sub(x[2].xor(not(x[1]).shl(19)));
+        x[4] = x[4].xor(x[3]);
+        x[5] = x[5].add(x[4]);
+        x[6] = x[6].sub(x[5].xor(not(x[4]).shr(23)));
+        x[7] = x[7].xor(x[6]);
+        x[0] = x[0].add(x[7]);
+        x[1] = x[1].sub(x[0].xor(not(x[7]).shl(19)));
+        x[2] = x[2].xor(x[1]);
+        x[3] = x[3].add(x[2]);
+        x[4] = x[4].sub(x[3].xor(not(x[2]).shr(23)));
+        x[5] = x[5].xor(x[4]);
+        x[6] = x[6].add(x[5]);
+        x[7] = x[7].sub(x[6].xor(V::splat(0x0123456789ABCDEF)));
+    }
+
+    #[inline(always)]
+    unsafe fn pass<V: Vector>(
+        a: &mut V,
+        b: &mut V,
+        c: &mut V,
+        x: &[V; 8],
+        mul: u64,
+        tables: &SboxTables,
+    ) {
+        round(a, b, c, x[0], mul, tables);
+        round(b, c, a, x[1], mul, tables);
+        round(c, a, b, x[2], mul, tables);
+        round(a, b, c, x[3], mul, tables);
+        round(b, c, a, x[4], mul, tables);
+        round(c, a, b, x[5], mul, tables);
+        round(a, b, c, x[6], mul, tables);
+        round(b, c, a, x[7], mul, tables);
+    }
+
+    #[inline(always)]
+    unsafe fn round<V: Vector>(
+        a: &mut V,
+        b: &mut V,
+        c: &mut V,
+        x: V,
+        mul: u64,
+        tables: &SboxTables,
+    ) {
+        let [t1, t2, t3, t4] = &tables.0;
+        *c = c.xor(x);
+        let byte = |n: i64| c.shr(8 * n).and(V::splat(0xFF));
+        *a = a.sub(
+            V::gather(t1, byte(0))
+                .xor(V::gather(t2, byte(2)))
+                .xor(V::gather(t3, byte(4)))
+                .xor(V::gather(t4, byte(6))),
+        );
+        *b = b.add(
+            V::gather(t4, byte(1))
+                .xor(V::gather(t3, byte(3)))
+                .xor(V::gather(t2, byte(5)))
+                .xor(V::gather(t1, byte(7))),
+        );
+        // There is no 64-bit vector multiply before AVX-512DQ, but the
+        // multipliers are all small.
+        *b = match mul {
+            5 => b.shl(2).add(*b),
+            7 => b.shl(3).sub(*b),
+            9 => b.shl(3).add(*b),
+            _ => unreachable!("unsupported pass multiplier {}", mul),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest;
+
+    /// Messages of many different lengths, so lanes finish at different times.
+    fn messages() -> Vec<Vec<u8>> {
+        (0..100)
+            .map(|i| (0..(i * 37) % 300).map(|j| (i * 13 + j) as u8).collect())
+            .collect()
+    }
+
+    fn check(hash: fn(&[&[u8]]) -> Vec<TigerDigest>) {
+        let messages = messages();
+        let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+        let expected: Vec<TigerDigest> = refs.iter().map(|m| digest(m)).collect();
+        assert_eq!(hash(&refs), expected);
+        assert_eq!(hash(&refs[..3]), expected[..3]);
+        assert_eq!(hash(&[]), vec![]);
+    }
+
+    #[test]
+    fn test_hash_many() {
+        check(hash_many);
+    }
+
+    #[test]
+    fn test_scalar() {
+        check(hash_many_scalar);
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(feature = "ct")))]
+    #[test]
+    fn test_avx2() {
+        if is_x86_feature_detected!("avx2") {
+            check(|messages| unsafe { x86::hash_many_avx2(messages) });
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(feature = "ct")))]
+    #[test]
+    fn test_avx512() {
+        if is_x86_feature_detected!("avx512f") {
+            check(|messages| unsafe { x86::hash_many_avx512(messages) });
+        }
+    }
+}
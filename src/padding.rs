@@ -0,0 +1,122 @@
+use std::slice::ChunksExact;
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::utils::{read_block, write_u64};
+
+/// Iterates over the blocks of a message with Tiger's padding applied: a
+/// `0x01` byte (`0x80` for Tiger2), zeros up to 56 bytes mod 64 and the bit
+/// length as a little-endian `u64`.
+pub(crate) struct PaddedBlocks<'a> {
+    blocks: ChunksExact<'a, u8>,
+    tail: [u8; 128],
+    tail_len: usize,
+    tail_pos: usize,
+}
+
+impl<'a> PaddedBlocks<'a> {
+    pub(crate) fn new(message: &'a [u8]) -> Self {
+        Self::with_len(message, message.len() as u64, 0x01)
+    }
+
+    /// Pads `message`, which ends a longer message of `message_len` bytes,
+    /// starting the padding with `pad`.
+    pub(crate) fn with_len(message: &'a [u8], message_len: u64, pad: u8) -> Self {
+        let blocks = message.chunks_exact(64);
+        let (tail, tail_len) = padded_tail(blocks.remainder(), message_len, pad);
+        Self {
+            blocks,
+            tail,
+            tail_len,
+            tail_pos: 0,
+        }
+    }
+}
+
+/// The last partial block of a message of `message_len` bytes, `remainder`,
+/// followed by the padding starting with `pad`, and the length of the result.
+///
+/// The padding takes one extra block, or two if fewer than 9 bytes are left in
+/// the last one.
+pub(crate) const fn padded_tail(remainder: &[u8], message_len: u64, pad: u8) -> ([u8; 128], usize) {
+    let mut tail = [0u8; 128];
+    let (head, _) = tail.split_at_mut(remainder.len());
+    head.copy_from_slice(remainder);
+    tail[remainder.len()] = pad;
+    let tail_len = if remainder.len() < 56 { 64 } else { 128 };
+    let (_, bit_len) = tail.split_at_mut(tail_len - 8);
+    let (bit_len, _) = bit_len.split_at_mut(8);
+    bit_len.copy_from_slice(&write_u64(message_len << 3));
+    (tail, tail_len)
+}
+
+impl Iterator for PaddedBlocks<'_> {
+    type Item = [u64; 8];
+
+    fn next(&mut self) -> Option<[u64; 8]> {
+        if let Some(block) = self.blocks.next() {
+            return Some(read_block(block.try_into().unwrap()));
+        }
+        if self.tail_pos == self.tail_len {
+            return None;
+        }
+        let block = &self.tail[self.tail_pos..self.tail_pos + 64];
+        self.tail_pos += 64;
+        Some(read_block(block.try_into().unwrap()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.blocks.len() + (self.tail_len - self.tail_pos) / 64;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for PaddedBlocks<'_> {}
+
+#[cfg(feature = "zeroize")]
+impl Drop for PaddedBlocks<'_> {
+    fn drop(&mut self) {
+        self.tail.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_count() {
+        for (len, blocks) in [
+            (0, 1),
+            (55, 1),
+            (56, 2),
+            (63, 2),
+            (64, 2),
+            (119, 2),
+            (120, 3),
+        ] {
+            let message = vec![0xAB; len];
+            let padded = PaddedBlocks::new(&message);
+            assert_eq!(padded.len(), blocks, "length {}", len);
+            assert_eq!(padded.count(), blocks, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_long_message_length() {
+        // The full 64-bit byte count goes into the padding, not just its low
+        // 32 bits.
+        let blocks: Vec<[u64; 8]> = PaddedBlocks::with_len(b"", 1 << 32, 0x01).collect();
+        assert_eq!(blocks[0][7], 1 << 35);
+    }
+
+    #[test]
+    fn test_padding() {
+        let blocks: Vec<[u64; 8]> = PaddedBlocks::new(b"abc").collect();
+        assert_eq!(blocks, vec![[0x01636261, 0, 0, 0, 0, 0, 0, 24]]);
+
+        let blocks: Vec<[u64; 8]> = PaddedBlocks::with_len(b"abc", 67, 0x80).collect();
+        assert_eq!(blocks, vec![[0x80636261, 0, 0, 0, 0, 0, 0, 536]]);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::utils::{generate_sboxes, SboxTables, TIGER_PASSES};
+use crate::{digest_with_tables, Tables, TigerDigest, TigerState};
+
+/// A Tiger hasher that uses its own S-boxes instead of the standard tables.
+///
+/// This is meant for experiments and cryptanalysis; digests computed with
+/// anything but the standard tables are not Tiger digests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TigerWithSboxes {
+    sboxes: Box<[[u8; 8]; 1024]>,
+    tables: Box<Tables>,
+}
+
+impl TigerWithSboxes {
+    /// Creates a hasher using `sboxes`, laid out like [`SBOXES`](crate::SBOXES).
+    pub fn new(sboxes: [[u8; 8]; 1024]) -> Self {
+        Self {
+            tables: Box::new(Tables::new(SboxTables::from_sboxes(&sboxes))),
+            sboxes: Box::new(sboxes),
+        }
+    }
+
+    /// Creates a hasher using tables generated by [`generate_sboxes`].
+    pub fn from_seed(seed: &[u8; 64], passes: usize) -> Self {
+        Self::new(generate_sboxes(seed, passes))
+    }
+
+    /// Returns the tables used by this hasher.
+    pub fn sboxes(&self) -> &[[u8; 8]; 1024] {
+        &self.sboxes
+    }
+
+    /// Computes the digest of `bytes` with these tables.
+    pub fn digest(&self, bytes: &[u8]) -> TigerDigest {
+        self.digest_with_passes::<TIGER_PASSES>(bytes)
+    }
+
+    /// Computes the `PASSES`-pass digest of `bytes` with these tables.
+    pub fn digest_with_passes<const PASSES: usize>(&self, bytes: &[u8]) -> TigerDigest {
+        digest_with_tables::<PASSES>(bytes, &self.tables)
+    }
+
+    /// Runs the compression function with these tables on a single block.
+    pub fn compress_block(&self, state: &mut TigerState, block: &[u8; 64]) {
+        state.compress_block_with_tables::<TIGER_PASSES>(block, &self.tables);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{generate_sbox_tables, SBOX_GENERATION_PASSES, SBOX_SEED};
+    use crate::{digest, SBOXES, TABLES};
+
+    #[test]
+    fn test_default_parameters() {
+        assert_eq!(generate_sboxes(SBOX_SEED, SBOX_GENERATION_PASSES), SBOXES);
+
+        let standard = TigerWithSboxes::from_seed(SBOX_SEED, SBOX_GENERATION_PASSES);
+        for message in [&b""[..], b"abc", &[0x42; 200]] {
+            assert_eq!(standard.digest(message), digest(message));
+        }
+
+        let block = [0x33; 64];
+        let (mut expected, mut actual) = (TigerState::new(), TigerState::new());
+        expected.compress_block(&block);
+        standard.compress_block(&mut actual, &block);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_tables() {
+        assert_eq!(TABLES.wide, SboxTables::from_sboxes(&SBOXES));
+        assert_eq!(TABLES.wide.to_sboxes(), SBOXES);
+        assert_eq!(
+            generate_sbox_tables(SBOX_SEED, SBOX_GENERATION_PASSES),
+            TABLES.wide
+        );
+        assert_eq!(TABLES.wide.0[1][0], 0xE6A6BE5A05A12138);
+        for table in &TABLES.wide.0 {
+            assert_eq!(table.as_ptr() as usize % 64, 0);
+        }
+    }
+
+    #[test]
+    fn test_custom_parameters() {
+        let fewer_passes = generate_sboxes(SBOX_SEED, 1);
+        assert_ne!(fewer_passes, SBOXES);
+
+        let mut seed = *SBOX_SEED;
+        seed[0] ^= 1;
+        let other_seed = TigerWithSboxes::from_seed(&seed, SBOX_GENERATION_PASSES);
+        assert_ne!(*other_seed.sboxes(), SBOXES);
+        assert_ne!(other_seed.digest(b"abc"), digest(b"abc"));
+
+        // Every column of every table stays a permutation of 0..=255.
+        for table in other_seed.sboxes().chunks_exact(256) {
+            for col in 0..8 {
+                let mut seen = [false; 256];
+                table
+                    .iter()
+                    .for_each(|entry| seen[entry[col] as usize] = true);
+                assert!(seen.iter().all(|&s| s));
+            }
+        }
+    }
+}
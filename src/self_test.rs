@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::utils::find_sbox_mismatch;
+use crate::{digest, DigestOrder, TigerDigest, SBOXES};
+
+/// Known-answer vectors checked by [`self_test`], in canonical order.
+const KNOWN_ANSWERS: [(&str, &str); 6] = [
+    ("", "3293AC630C13F0245F92BBB1766E16167A4E58492DDE73F3"),
+    ("a", "77BEFBEF2E7EF8AB2EC8F93BF587A7FC613E247F5F247809"),
+    ("abc", "2AAB1484E8C158F2BFB8C5FF41B57A525129131C957B5F93"),
+    (
+        "message digest",
+        "D981F8CB78201A950DCF3048751E441C517FCA1AA55A29F6",
+    ),
+    (
+        "abcdefghijklmnopqrstuvwxyz",
+        "1714A472EEE57D30040412BFCC55032A0B11602FF37BEEE9",
+    ),
+    (
+        "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "0F7BF9A19B9C58F2B7610DF7E84F0AC3A71C631E7B53F78E",
+    ),
+];
+
+/// Error returned by [`self_test`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelfTestError {
+    /// An S-box does not match the reference tables. The index is 0 for t1
+    /// through 3 for t4.
+    SboxMismatch(usize),
+    /// A known-answer test produced the wrong digest.
+    DigestMismatch {
+        message: &'static str,
+        expected: TigerDigest,
+        actual: TigerDigest,
+    },
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SboxMismatch(table) => write!(
+                f,
+                "S-box t{} does not match the reference tables",
+                table + 1
+            ),
+            Self::DigestMismatch {
+                message,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "digest of {:?} is {}, expected {}",
+                message, actual, expected
+            ),
+        }
+    }
+}
+
+impl Error for SelfTestError {}
+
+/// Power-on self test.
+///
+/// Checks the compiled-in S-boxes against fingerprints of the tables
+/// published with Tiger and runs a set of known-answer digests. Call this once
+/// at startup if you need to guarantee the hash has not been miscompiled.
+pub fn self_test() -> Result<(), SelfTestError> {
+    if let Some(table) = find_sbox_mismatch(&SBOXES) {
+        return Err(SelfTestError::SboxMismatch(table));
+    }
+    for (message, expected) in KNOWN_ANSWERS {
+        let expected = TigerDigest::from_hex(expected, DigestOrder::Canonical).unwrap();
+        let actual = digest(message.as_bytes());
+        if actual != expected {
+            return Err(SelfTestError::DigestMismatch {
+                message,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{generate_sboxes, sbox_fingerprint, SBOX_FINGERPRINTS, SBOX_SEED};
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn test_detects_modified_sboxes() {
+        let mut sboxes = SBOXES;
+        sboxes.swap(600, 601);
+        assert_eq!(find_sbox_mismatch(&sboxes), Some(2));
+        assert_ne!(sbox_fingerprint(&sboxes[512..768]), SBOX_FINGERPRINTS[2]);
+
+        assert_eq!(find_sbox_mismatch(&generate_sboxes(SBOX_SEED, 4)), Some(0));
+    }
+
+    /// Entries from throughout the published tables, so a difference between
+    /// the first and last entries doesn't go unnoticed if a fingerprint
+    /// constant is ever wrong too.
+    const REFERENCE_ENTRIES: [[(usize, u64); 6]; 4] = [
+        [
+            (1, 0xAC424B03E243A8EC),
+            (37, 0xDB6DCF8AFC357BF1),
+            (100, 0x24F6EC65B528D56C),
+            (128, 0x0E5715A2D149AA23),
+            (201, 0xCEBB400F137B8AA5),
+            (254, 0xA6300F170BDC4820),
+        ],
+        [
+            (1, 0xB5A122A5B4F87C98),
+            (37, 0xA7E25D09078AC09B),
+            (100, 0x0F7F903717D06580),
+            (128, 0x8F7CB9FE5D7A4578),
+            (201, 0x2577018134BE98C4),
+            (254, 0xD62A2EABC0977179),
+        ],
+        [
+            (1, 0x487FD5C66FF29281),
+            (37, 0xD9C42CA47086FCB7),
+            (100, 0xA8D8426E8B6A83EA),
+            (128, 0x8476FC1D4F387B58),
+            (201, 0xBC3D86A3E36210C9),
+            (254, 0xD3DC3BEF265B0F70),
+        ],
+        [
+            (1, 0x1A46C1A9FA1B59F5),
+            (37, 0xC7FA975FCB243847),
+            (100, 0x0F6F64FCEC15F389),
+            (128, 0x6E3480F60F4A265F),
+            (201, 0x80CF9BEDE388FB63),
+            (254, 0xC83223F1720AEF96),
+        ],
+    ];
+
+    #[test]
+    fn test_reference_entries() {
+        for (table, entries) in REFERENCE_ENTRIES.iter().enumerate() {
+            for &(index, expected) in entries {
+                let entry = u64::from_le_bytes(SBOXES[table * 256 + index]);
+                assert_eq!(entry, expected, "t{}[{}]", table + 1, index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            SelfTestError::SboxMismatch(3).to_string(),
+            "S-box t4 does not match the reference tables"
+        );
+    }
+}
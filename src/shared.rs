@@ -0,0 +1,70 @@
+//! Helpers used both by the library and by the command-line tools, which
+//! include this file through `#[path]` rather than duplicating it.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `work` on every item using up to `jobs` threads and passes each
+/// result to `output` in the order of `items`.
+///
+/// Stops handing out items once `output` returns an error, and returns that
+/// error.
+pub(crate) fn run_parallel<T, R, E>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut output: impl FnMut(&T, R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            let (next, work, sender) = (&next, &work, sender.clone());
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                // The receiver is gone once `output` has failed.
+                if sender.send((index, work(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut finished = HashMap::new();
+        let mut expected = 0;
+        for (index, result) in receiver {
+            finished.insert(index, result);
+            while let Some(result) = finished.remove(&expected) {
+                if let Err(e) = output(&items[expected], result) {
+                    next.store(items.len(), Ordering::Relaxed);
+                    return Err(e);
+                }
+                expected += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Device and inode number of a file, which identify it across hard links.
+/// `None` where the platform has no such numbers.
+#[cfg(unix)]
+pub(crate) fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
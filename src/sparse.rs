@@ -0,0 +1,219 @@
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::hasher::read_chunks;
+use crate::{Tiger, TigerTree};
+
+/// A hasher that can take runs of zero bytes without them being read.
+pub(crate) trait ZeroSink {
+    fn update(&mut self, bytes: &[u8]);
+    fn update_zeros(&mut self, len: u64);
+}
+
+impl ZeroSink for Tiger {
+    fn update(&mut self, bytes: &[u8]) {
+        Tiger::update(self, bytes);
+    }
+
+    fn update_zeros(&mut self, len: u64) {
+        Tiger::update_zeros(self, len);
+    }
+}
+
+impl ZeroSink for TigerTree {
+    fn update(&mut self, bytes: &[u8]) {
+        TigerTree::update(self, bytes);
+    }
+
+    fn update_zeros(&mut self, len: u64) {
+        TigerTree::update_zeros(self, len);
+    }
+}
+
+/// Passes the whole contents of `file`, from its start, to `sink` and
+/// returns the number of bytes passed.
+///
+/// Holes, as reported by `SEEK_DATA` and `SEEK_HOLE` on Linux, are passed as
+/// runs of zeros without being read, and so are chunks that read as all
+/// zeros. The length of a block device is found by seeking to its end. The
+/// last run of data is read until end of file, so like a reader this
+/// includes anything appended meanwhile; a file that shrinks before then
+/// fails with [`UnexpectedEof`](io::ErrorKind::UnexpectedEof). Anything but
+/// a regular file or block device is read as a stream from where it is.
+pub(crate) fn read_sparse(mut file: &File, sink: &mut impl ZeroSink) -> io::Result<u64> {
+    let metadata = file.metadata()?;
+    let len = if metadata.is_file() {
+        metadata.len()
+    } else if is_block_device(&metadata) {
+        file.seek(SeekFrom::End(0))?
+    } else {
+        return read_chunks(file, |chunk| feed(sink, chunk));
+    };
+
+    let mut pos = 0;
+    loop {
+        let (data, hole) = next_data(file, pos, len)?;
+        sink.update_zeros(data - pos);
+        file.seek(SeekFrom::Start(data))?;
+        if hole >= len {
+            return Ok(data + read_chunks(file, |chunk| feed(sink, chunk))?);
+        }
+        let read = read_chunks(file.take(hole - data), |chunk| feed(sink, chunk))?;
+        if read < hole - data {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while it was read",
+            ));
+        }
+        pos = hole;
+    }
+}
+
+/// Passes `chunk` to `sink`, as a run of zeros if that's all it holds.
+fn feed(sink: &mut impl ZeroSink, chunk: &[u8]) -> io::Result<()> {
+    if chunk.iter().all(|&byte| byte == 0) {
+        sink.update_zeros(chunk.len() as u64);
+    } else {
+        sink.update(chunk);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_block_device(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    metadata.file_type().is_block_device()
+}
+
+#[cfg(not(unix))]
+fn is_block_device(_: &Metadata) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+mod holes {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    const SEEK_DATA: i32 = 3;
+    const SEEK_HOLE: i32 = 4;
+    const ENXIO: i32 = 6;
+
+    extern "C" {
+        // glibc's `lseek` takes a 32-bit offset on 32-bit targets, but musl's
+        // offsets are always 64 bits wide and it has no `lseek64` to link.
+        #[cfg_attr(not(target_env = "musl"), link_name = "lseek64")]
+        fn lseek(fd: i32, offset: i64, whence: i32) -> i64;
+    }
+
+    fn seek(file: &File, offset: u64, whence: i32) -> io::Result<u64> {
+        let offset = i64::try_from(offset).map_err(|_| io::ErrorKind::InvalidInput)?;
+        // SAFETY: lseek only takes plain values, and the descriptor is open
+        // for as long as `file` is borrowed.
+        let result = unsafe { lseek(file.as_raw_fd(), offset, whence) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(result as u64)
+    }
+
+    /// The first run of data at or after `from`, as `(start, end)`, both at
+    /// most `len`.
+    pub(super) fn next_data(file: &File, from: u64, len: u64) -> io::Result<(u64, u64)> {
+        let data = match seek(file, from, SEEK_DATA) {
+            Ok(data) => data.min(len),
+            // Only a hole is left.
+            Err(e) if e.raw_os_error() == Some(ENXIO) => return Ok((len, len)),
+            // Holes can't be found here; read everything.
+            Err(_) => return Ok((from, len)),
+        };
+        let hole = seek(file, data, SEEK_HOLE).map_or(len, |hole| hole.min(len));
+        Ok((data, hole))
+    }
+}
+
+#[cfg(target_os = "linux")]
+use holes::next_data;
+
+#[cfg(not(target_os = "linux"))]
+fn next_data(_: &File, from: u64, len: u64) -> io::Result<(u64, u64)> {
+    Ok((from, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::{digest, tree_hash, DigestKind};
+    use std::fs;
+    use std::io::Write;
+
+    /// Records what it is given, merging neighbouring runs of the same sort.
+    #[derive(Default)]
+    struct Runs(Vec<(bool, u64)>);
+
+    impl ZeroSink for Runs {
+        fn update(&mut self, bytes: &[u8]) {
+            self.push(false, bytes.len() as u64);
+        }
+
+        fn update_zeros(&mut self, len: u64) {
+            self.push(true, len);
+        }
+    }
+
+    impl Runs {
+        fn push(&mut self, zeros: bool, len: u64) {
+            match self.0.last_mut() {
+                Some((last, total)) if *last == zeros => *total += len,
+                _ if len > 0 => self.0.push((zeros, len)),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_read_sparse() {
+        let root = temp_dir("read");
+        let path = root.join("image");
+        let mut file = File::create(&path).unwrap();
+        // Data, a hole of a few MiB, data again, then a trailing hole.
+        file.write_all(&[1; 5000]).unwrap();
+        file.seek(SeekFrom::Start(3 << 20)).unwrap();
+        file.write_all(&[2; 70_000]).unwrap();
+        file.set_len(5 << 20).unwrap();
+        drop(file);
+
+        let contents = fs::read(&path).unwrap();
+        let file = File::open(&path).unwrap();
+        for kind in [DigestKind::Tiger, DigestKind::Tree] {
+            let expected = match kind {
+                DigestKind::Tiger => digest(&contents),
+                DigestKind::Tree => tree_hash(&contents),
+            };
+            assert_eq!(
+                kind.digest_file(&file).unwrap(),
+                (5 << 20, expected),
+                "{:?}",
+                kind
+            );
+        }
+
+        // Holes, and zeros that had to be read, both go in as runs of zeros.
+        let mut runs = Runs::default();
+        assert_eq!(read_sparse(&file, &mut runs).unwrap(), 5 << 20);
+        assert_eq!(runs.0.iter().map(|&(_, len)| len).sum::<u64>(), 5 << 20);
+        let zeros: Vec<bool> = runs.0.iter().map(|&(zeros, _)| zeros).collect();
+        assert_eq!(zeros, [false, true, false, true]);
+
+        fs::write(root.join("empty"), b"").unwrap();
+        let empty = File::open(root.join("empty")).unwrap();
+        assert_eq!(
+            DigestKind::Tiger.digest_file(&empty).unwrap(),
+            (0, digest(b""))
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use crate::utils::{read_block, write_words, START_VALUES, TIGER_PASSES};
+use crate::{compress, Tables, TABLES};
+
+/// The raw Tiger chaining state `(a, b, c)`.
+///
+/// This is a low-level API for building your own constructions on top of the
+/// Tiger compression function (custom IVs, Davies–Meyer experiments, different
+/// padding schemes, ...). It does no padding and no length encoding: every call
+/// to [`TigerState::compress_block`] feeds exactly one 64-byte block into the
+/// state. Use [`hash`](crate::hash) to compute ordinary Tiger digests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TigerState([u64; 3]);
+
+impl TigerState {
+    /// Creates a state holding the standard Tiger initial values.
+    pub const fn new() -> Self {
+        Self(START_VALUES)
+    }
+
+    /// Creates a state from an arbitrary initial value.
+    pub const fn from_iv(iv: [u64; 3]) -> Self {
+        Self(iv)
+    }
+
+    /// Returns the chaining values as 64-bit words.
+    pub const fn words(&self) -> [u64; 3] {
+        self.0
+    }
+
+    /// Runs the compression function on a single 64-byte block.
+    ///
+    /// The block is read as eight little-endian 64-bit words, as in the
+    /// reference implementation.
+    pub fn compress_block(&mut self, block: &[u8; 64]) {
+        self.compress_block_with_passes::<TIGER_PASSES>(block);
+    }
+
+    /// Runs a `PASSES`-pass variant of the compression function on a single
+    /// 64-byte block.
+    pub fn compress_block_with_passes<const PASSES: usize>(&mut self, block: &[u8; 64]) {
+        self.compress_block_with_tables::<PASSES>(block, &TABLES);
+    }
+
+    pub(crate) fn compress_block_with_tables<const PASSES: usize>(
+        &mut self,
+        block: &[u8; 64],
+        tables: &Tables,
+    ) {
+        compress::<PASSES>(&read_block(block), &mut self.0, tables);
+    }
+
+    /// Serializes the state as 24 bytes, each word in little-endian order.
+    ///
+    /// For a state that has processed a fully padded message this is the
+    /// canonical Tiger digest.
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0u8; 24];
+        write_words(&self.0, &mut bytes);
+        bytes
+    }
+}
+
+impl Default for TigerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for TigerState {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl From<[u64; 3]> for TigerState {
+    fn from(iv: [u64; 3]) -> Self {
+        Self::from_iv(iv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash;
+    use crate::utils::compress_with_sbox;
+
+    /// Pads `message` the way [`hash`] does and runs it through a
+    /// [`TigerState`] block by block.
+    fn hash_with_state(message: &[u8]) -> [u8; 24] {
+        let mut padded = message.to_vec();
+        padded.push(0x01);
+        while padded.len() % 64 != 56 {
+            padded.push(0x00);
+        }
+        padded.extend_from_slice(&((message.len() as u64) << 3).to_le_bytes());
+
+        let mut state = TigerState::new();
+        for block in padded.chunks_exact(64) {
+            state.compress_block(block.try_into().unwrap());
+        }
+        state.to_bytes()
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02X}", b)).collect()
+    }
+
+    #[test]
+    fn test_matches_hash() {
+        for len in 0..200 {
+            let message: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            assert_eq!(
+                to_hex(&hash_with_state(&message)),
+                hash(message.clone()),
+                "length {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_iv() {
+        assert_eq!(TigerState::from_iv(START_VALUES), TigerState::new());
+        assert_eq!(TigerState::default().words(), START_VALUES);
+
+        let block = [0x5A; 64];
+        let mut standard = TigerState::new();
+        let mut custom = TigerState::from_iv([1, 2, 3]);
+        standard.compress_block(&block);
+        custom.compress_block(&block);
+        assert_ne!(standard, custom);
+
+        let mut words = [1, 2, 3];
+        compress_with_sbox::<TIGER_PASSES>(&[0x5A5A5A5A5A5A5A5A; 8], &mut words, &TABLES.wide);
+        assert_eq!(custom.words(), words);
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let state = TigerState::from_iv([0x0123456789ABCDEF, 0, u64::MAX]);
+        let bytes = state.to_bytes();
+        assert_eq!(bytes[..8], [0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01]);
+        assert_eq!(bytes[8..16], [0; 8]);
+        assert_eq!(bytes[16..], [0xFF; 8]);
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the unit tests.
+
+// The `ct` bench includes `xorshift` and the binaries include `temp_dir`,
+// since they can't see `cfg(test)` items.
+mod temp_dir;
+mod xorshift;
+
+pub(crate) use temp_dir::temp_dir;
+pub(crate) use xorshift::next;
+
+use crate::utils::{compress_with_sbox, SboxTables};
+
+/// Checks that `compress` agrees with [`compress_with_sbox`] on `iterations`
+/// random blocks and states, or a hundredth as many under Miri.
+pub(crate) fn check_compress<const PASSES: usize>(
+    tables: &SboxTables,
+    seed: &mut u64,
+    iterations: usize,
+    compress: impl Fn(&[u64; 8], &mut [u64; 3]),
+) {
+    let iterations = if cfg!(miri) {
+        iterations / 100
+    } else {
+        iterations
+    };
+    for _ in 0..iterations {
+        let block: [u64; 8] = std::array::from_fn(|_| next(seed));
+        let state: [u64; 3] = std::array::from_fn(|_| next(seed));
+        let (mut expected, mut actual) = (state, state);
+        compress_with_sbox::<PASSES>(&block, &mut expected, tables);
+        compress(&block, &mut actual);
+        assert_eq!(actual, expected, "block {:X?}, state {:X?}", block, state);
+    }
+}
@@ -0,0 +1,7 @@
+/// xorshift64*, good enough to produce varied test inputs.
+pub(crate) fn next(seed: &mut u64) -> u64 {
+    *seed ^= *seed >> 12;
+    *seed ^= *seed << 25;
+    *seed ^= *seed >> 27;
+    seed.wrapping_mul(0x2545F4914F6CDD1D)
+}
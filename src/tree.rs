@@ -0,0 +1,444 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use crate::hasher::read_chunks;
+use crate::sparse::read_sparse;
+use crate::{digest, Tiger, TigerDigest};
+
+/// Size of the data blocks hashed into the leaves of a Tiger tree.
+pub const TREE_LEAF_SIZE: usize = 1024;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Default limit on the nodes kept on the lowest level returned by
+/// [`TigerTree::finalize_levels`].
+const DEFAULT_MAX_NODES: usize = 1024;
+
+/// Computes the Tiger tree hash (TTH) root of `bytes`.
+pub fn tree_hash(bytes: &[u8]) -> TigerDigest {
+    let mut tree = TigerTree::new();
+    tree.update(bytes);
+    tree.finalize()
+}
+
+/// Which digest of a whole file or message to compute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DigestKind {
+    /// The Tiger digest.
+    #[default]
+    Tiger,
+    /// The Tiger tree hash root, see [`TigerTree`].
+    Tree,
+}
+
+impl DigestKind {
+    /// Computes this digest of everything `reader` yields until end of file
+    /// and returns it with the number of bytes read.
+    pub fn digest_reader<R: Read>(self, reader: R) -> io::Result<(u64, TigerDigest)> {
+        match self {
+            Self::Tiger => {
+                let mut hasher = Tiger::new();
+                Ok((hasher.update_reader(reader)?, hasher.finalize()))
+            }
+            Self::Tree => {
+                let mut tree = TigerTree::new();
+                Ok((tree.update_reader(reader)?, tree.finalize()))
+            }
+        }
+    }
+
+    /// Computes this digest of the whole contents of `file` and returns it
+    /// with the file's length. Holes in sparse files are not read; see
+    /// [`Tiger::update_file`].
+    pub fn digest_file(self, file: &File) -> io::Result<(u64, TigerDigest)> {
+        match self {
+            Self::Tiger => {
+                let mut hasher = Tiger::new();
+                Ok((hasher.update_file(file)?, hasher.finalize()))
+            }
+            Self::Tree => {
+                let mut tree = TigerTree::new();
+                Ok((tree.update_file(file)?, tree.finalize()))
+            }
+        }
+    }
+}
+
+/// Incremental Tiger tree hasher, as used by THEX, Direct Connect and
+/// Gnutella.
+///
+/// The message is split into 1 KiB blocks. A leaf is the Tiger digest of
+/// `0x00` followed by a block, and an internal node is the Tiger digest of
+/// `0x01` followed by its two children. A node without a sibling moves up a
+/// level unchanged. An empty message has a single leaf for the empty block.
+///
+/// Besides the root, the hasher keeps one level of the tree for
+/// [`finalize_levels`](Self::finalize_levels). It starts with the leaves and
+/// moves up a level whenever it would hold more than the limit given to
+/// [`with_max_nodes`](Self::with_max_nodes), so memory stays bounded for any
+/// message length.
+#[derive(Clone)]
+pub struct TigerTree {
+    leaf: Tiger,
+    leaf_len: usize,
+    /// Complete subtrees of `2^height` leaves each, left to right.
+    nodes: Vec<TigerDigest>,
+    height: u32,
+    /// Complete subtrees that follow `nodes`, with decreasing heights all
+    /// lower than `height`.
+    pending: Vec<(u32, TigerDigest)>,
+    max_nodes: usize,
+}
+
+impl TigerTree {
+    /// Creates a tree hasher for an empty message, keeping a level of at most
+    /// 1024 nodes.
+    pub fn new() -> Self {
+        Self::with_max_nodes(DEFAULT_MAX_NODES)
+    }
+
+    /// Creates a tree hasher for an empty message whose lowest kept level
+    /// has at most `max_nodes` nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_nodes` is zero.
+    pub fn with_max_nodes(max_nodes: usize) -> Self {
+        assert!(max_nodes > 0, "a tree level needs at least one node");
+        Self {
+            leaf: leaf_hasher(),
+            leaf_len: 0,
+            nodes: Vec::new(),
+            height: 0,
+            pending: Vec::new(),
+            max_nodes,
+        }
+    }
+
+    /// Appends `bytes` to the message.
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let take = bytes.len().min(TREE_LEAF_SIZE - self.leaf_len);
+            self.leaf.update(&bytes[..take]);
+            self.leaf_len += take;
+            bytes = &bytes[take..];
+            if self.leaf_len == TREE_LEAF_SIZE {
+                self.finish_leaf();
+            }
+        }
+    }
+
+    /// Appends `len` zero bytes to the message.
+    ///
+    /// Every whole block of zeros has the same leaf, which is only computed
+    /// once.
+    pub fn update_zeros(&mut self, mut len: u64) {
+        if self.leaf_len > 0 {
+            let take = len.min((TREE_LEAF_SIZE - self.leaf_len) as u64);
+            self.leaf.update_zeros(take);
+            self.leaf_len += take as usize;
+            len -= take;
+            if self.leaf_len < TREE_LEAF_SIZE {
+                return;
+            }
+            self.finish_leaf();
+        }
+
+        static ZERO_LEAF: OnceLock<TigerDigest> = OnceLock::new();
+        let zero_leaf = *ZERO_LEAF.get_or_init(|| {
+            let mut leaf = leaf_hasher();
+            leaf.update_zeros(TREE_LEAF_SIZE as u64);
+            leaf.finalize()
+        });
+        for _ in 0..len / TREE_LEAF_SIZE as u64 {
+            self.push_leaf(zero_leaf);
+        }
+        let rest = len % TREE_LEAF_SIZE as u64;
+        self.leaf.update_zeros(rest);
+        self.leaf_len = rest as usize;
+    }
+
+    /// Appends everything `reader` yields until end of file and returns the
+    /// number of bytes read.
+    pub fn update_reader<R: Read>(&mut self, reader: R) -> io::Result<u64> {
+        read_chunks(reader, |chunk| {
+            self.update(chunk);
+            Ok(())
+        })
+    }
+
+    /// Appends the whole contents of `file` and returns its length, skipping
+    /// holes as [`Tiger::update_file`] does.
+    pub fn update_file(&mut self, file: &File) -> io::Result<u64> {
+        read_sparse(file, self)
+    }
+
+    /// Returns the root of the tree.
+    pub fn finalize(self) -> TigerDigest {
+        self.finalize_levels()[0][0]
+    }
+
+    /// Returns the top levels of the tree, starting with the one holding the
+    /// root.
+    ///
+    /// The last level is the lowest one with at most the configured number
+    /// of nodes. Concatenating the levels gives the breadth-first
+    /// serialization used by THEX.
+    pub fn finalize_levels(mut self) -> Vec<Vec<TigerDigest>> {
+        if self.leaf_len > 0 || (self.nodes.is_empty() && self.pending.is_empty()) {
+            self.finish_leaf();
+        }
+
+        let mut level = std::mem::take(&mut self.nodes);
+        // The pending subtrees together form the last, incomplete node.
+        let tail = self.pending.iter().rev().map(|&(_, node)| node);
+        if let Some(last) = tail.reduce(|right, left| internal_node(&left, &right)) {
+            level.push(last);
+        }
+
+        let mut levels = vec![level];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let parents = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => internal_node(left, right),
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(parents);
+        }
+        // The incomplete node can take the first level one over the limit.
+        if levels[0].len() > self.max_nodes {
+            levels.remove(0);
+        }
+        levels.reverse();
+        levels
+    }
+
+    fn finish_leaf(&mut self) {
+        let leaf = std::mem::replace(&mut self.leaf, leaf_hasher()).finalize();
+        self.leaf_len = 0;
+        self.push_leaf(leaf);
+    }
+
+    /// Adds the next leaf, merging it into complete subtrees.
+    fn push_leaf(&mut self, mut leaf: TigerDigest) {
+        let mut height = 0;
+        while let Some(&(_, left)) = self.pending.last().filter(|&&(h, _)| h == height) {
+            self.pending.pop();
+            leaf = internal_node(&left, &leaf);
+            height += 1;
+        }
+        if height < self.height {
+            self.pending.push((height, leaf));
+            return;
+        }
+
+        // A carry only reaches `height` once every pending subtree has been
+        // merged, so `pending` is empty here.
+        self.nodes.push(leaf);
+        if self.nodes.len() > self.max_nodes {
+            let nodes = std::mem::take(&mut self.nodes);
+            let mut pairs = nodes.chunks_exact(2);
+            self.nodes = pairs
+                .by_ref()
+                .map(|pair| internal_node(&pair[0], &pair[1]))
+                .collect();
+            if let [node] = pairs.remainder() {
+                self.pending.push((self.height, *node));
+            }
+            self.height += 1;
+        }
+    }
+}
+
+impl Default for TigerTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TigerTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TigerTree").finish_non_exhaustive()
+    }
+}
+
+impl io::Write for TigerTree {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn leaf_hasher() -> Tiger {
+    let mut leaf = Tiger::new();
+    leaf.update(&[LEAF_PREFIX]);
+    leaf
+}
+
+fn internal_node(left: &TigerDigest, right: &TigerDigest) -> TigerDigest {
+    let mut bytes = [0u8; 49];
+    bytes[0] = NODE_PREFIX;
+    bytes[1..25].copy_from_slice(left.as_bytes());
+    bytes[25..].copy_from_slice(right.as_bytes());
+    digest(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the whole tree level by level, as THEX describes it.
+    fn reference_root(bytes: &[u8]) -> TigerDigest {
+        let mut level: Vec<TigerDigest> = if bytes.is_empty() {
+            vec![digest(&[LEAF_PREFIX])]
+        } else {
+            bytes
+                .chunks(TREE_LEAF_SIZE)
+                .map(|block| digest(&[&[LEAF_PREFIX], block].concat()))
+                .collect()
+        };
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => internal_node(left, right),
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    #[test]
+    fn test_vectors() {
+        let vectors: [(&[u8], &str); 4] = [
+            (b"", "LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ"),
+            (b"\0", "VK54ZIEEVTWNAUI5D5RDFIL37LX2IQNSTAXFKSA"),
+            (&[b'A'; 1024], "L66Q4YVNAFWVS23X2HJIRA5ZJ7WXR3F26RSASFA"),
+            (&[b'A'; 1025], "PZMRYHGY6LTBEH63ZWAHDORHSYTLO4LEFUIKHWY"),
+        ];
+        for (bytes, expected) in vectors {
+            assert_eq!(
+                tree_hash(bytes).to_base32(),
+                expected,
+                "{} bytes",
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        // Fewer cases under Miri, which is too slow for all of them.
+        let (leaves, limits) = if cfg!(miri) {
+            (5, &[1, 2][..])
+        } else {
+            (40, &[1, 2, 3, 4, 1024][..])
+        };
+        let data: Vec<u8> = (0..(leaves * TREE_LEAF_SIZE) as u32)
+            .map(|i| (i * 7 + i / 251) as u8)
+            .collect();
+        let lens = (0..leaves.min(10)).map(|leaves| leaves * TREE_LEAF_SIZE);
+        for len in lens
+            .clone()
+            .chain(lens.map(|len| len + 1))
+            .chain([data.len() - 1])
+        {
+            let expected = reference_root(&data[..len]);
+            for &max_nodes in limits {
+                let mut tree = TigerTree::with_max_nodes(max_nodes);
+                for piece in data[..len].chunks(700) {
+                    tree.update(piece);
+                }
+                let levels = tree.finalize_levels();
+                assert_eq!(levels[0], [expected], "{} bytes, {} nodes", len, max_nodes);
+                assert!(levels.last().unwrap().len() <= max_nodes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        // Five leaves: [l0 l1 l2 l3 l4] -> [n01 n23 l4] -> [n0123 l4] -> [root].
+        let data = [0x5Au8; 4 * TREE_LEAF_SIZE + 10];
+        let leaves: Vec<_> = data
+            .chunks(TREE_LEAF_SIZE)
+            .map(|block| digest(&[&[LEAF_PREFIX], block].concat()))
+            .collect();
+        let n01 = internal_node(&leaves[0], &leaves[1]);
+        let n23 = internal_node(&leaves[2], &leaves[3]);
+        let n0123 = internal_node(&n01, &n23);
+        let root = internal_node(&n0123, &leaves[4]);
+
+        let mut tree = TigerTree::new();
+        tree.update(&data);
+        assert_eq!(
+            tree.finalize_levels(),
+            [
+                vec![root],
+                vec![n0123, leaves[4]],
+                vec![n01, n23, leaves[4]],
+                leaves.clone()
+            ]
+        );
+
+        let mut tree = TigerTree::with_max_nodes(3);
+        tree.update(&data);
+        assert_eq!(
+            tree.finalize_levels(),
+            [
+                vec![root],
+                vec![n0123, leaves[4]],
+                vec![n01, n23, leaves[4]]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_digest_reader() {
+        let data = [7u8; 2000];
+        let tiger = DigestKind::Tiger.digest_reader(&data[..]).unwrap();
+        assert_eq!(tiger, (2000, digest(&data)));
+        let tree = DigestKind::Tree.digest_reader(&data[..]).unwrap();
+        assert_eq!(tree, (2000, tree_hash(&data)));
+    }
+
+    #[test]
+    fn test_update_reader() {
+        let data = vec![0xC3u8; 3 * TREE_LEAF_SIZE + 17];
+        let mut tree = TigerTree::new();
+        assert_eq!(tree.update_reader(&data[..]).unwrap(), data.len() as u64);
+        assert_eq!(tree.finalize(), tree_hash(&data));
+    }
+
+    #[test]
+    fn test_update_zeros() {
+        let mut data = vec![0x11u8; 100];
+        data.resize(100 + 5 * TREE_LEAF_SIZE + 30, 0);
+        data.extend_from_slice(&[0x22; 2000]);
+        for max_nodes in [1, 2, 1024] {
+            let mut tree = TigerTree::with_max_nodes(max_nodes);
+            tree.update(&data[..100]);
+            tree.update_zeros(5 * TREE_LEAF_SIZE as u64 + 30);
+            tree.update(&data[data.len() - 2000..]);
+            assert_eq!(tree.finalize(), reference_root(&data), "{}", max_nodes);
+        }
+
+        for len in [0, 1, TREE_LEAF_SIZE, 3 * TREE_LEAF_SIZE + 1] {
+            let mut tree = TigerTree::new();
+            tree.update_zeros(len as u64);
+            assert_eq!(tree.finalize(), reference_root(&vec![0; len]), "{}", len);
+        }
+    }
+}
@@ -0,0 +1,962 @@
+use std::alloc::{self, Layout};
+use std::ffi::{c_long, c_void};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::hasher::read_chunks;
+use crate::{DigestKind, Tiger, TigerDigest, TigerTree};
+
+const DEFAULT_BUFFERS: usize = 8;
+const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+/// Alignment of buffers, offsets and lengths that `O_DIRECT` asks for on
+/// any device.
+const DIRECT_ALIGN: usize = 4096;
+
+/// `io_uring_setup` and `io_uring_enter`, which have the same numbers on
+/// every architecture listed.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "powerpc64",
+    target_arch = "s390x",
+    target_arch = "loongarch64"
+))]
+const SYSCALLS: Option<(c_long, c_long)> = Some((425, 426));
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    target_arch = "powerpc64",
+    target_arch = "s390x",
+    target_arch = "loongarch64"
+)))]
+const SYSCALLS: Option<(c_long, c_long)> = None;
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "s390x",
+    target_arch = "loongarch64"
+))]
+const O_DIRECT: Option<i32> = Some(0o40000);
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+const O_DIRECT: Option<i32> = Some(0o200000);
+#[cfg(target_arch = "powerpc64")]
+const O_DIRECT: Option<i32> = Some(0o400000);
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "riscv64",
+    target_arch = "s390x",
+    target_arch = "loongarch64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "powerpc64"
+)))]
+const O_DIRECT: Option<i32> = None;
+
+const EINTR: i32 = 4;
+const EAGAIN: i32 = 11;
+const EBUSY: i32 = 16;
+const EINVAL: i32 = 22;
+
+const IORING_OFF_SQ_RING: c_long = 0;
+const IORING_OFF_CQ_RING: c_long = 0x800_0000;
+const IORING_OFF_SQES: c_long = 0x1000_0000;
+const IORING_ENTER_GETEVENTS: c_long = 1;
+const IORING_OP_READV: u8 = 1;
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const MAP_SHARED: i32 = 1;
+const MAP_POPULATE: i32 = 0x8000;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+extern "C" {
+    fn syscall(number: c_long, ...) -> c_long;
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: c_long,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+/// `struct io_uring_sqe`, with only the fields a read uses named.
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    rest: [u64; 3],
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+const _: () = assert!(std::mem::size_of::<Params>() == 120);
+const _: () = assert!(std::mem::size_of::<Sqe>() == 64);
+const _: () = assert!(std::mem::size_of::<Cqe>() == 16);
+
+/// A shared memory mapping of the ring.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: i32, len: usize, offset: c_long) -> io::Result<Self> {
+        // SAFETY: a fresh shared mapping of the ring's file descriptor, which
+        // doesn't touch any existing memory.
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED | MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    /// The `u32` the kernel shares at byte `offset`.
+    fn word(&self, offset: u32) -> &AtomicU32 {
+        assert!(offset as usize + 4 <= self.len);
+        // SAFETY: in bounds, and the kernel puts these words at 4-byte
+        // aligned offsets of a page-aligned mapping.
+        unsafe { &*self.ptr.add(offset as usize).cast::<AtomicU32>() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is ours and nothing borrows from it anymore.
+        unsafe { munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+/// An io_uring instance, used by one thread at a time.
+struct Ring {
+    fd: File,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    params: Params,
+    #[cfg(test)]
+    faults: Faults,
+}
+
+/// Failures for tests to inject into [`Ring::complete`], by the index of
+/// the completion counted over the ring's life.
+#[cfg(test)]
+#[derive(Clone, Copy, Debug, Default)]
+struct Faults {
+    /// Caps the bytes every read reports, making the reads short.
+    max_read: Option<i32>,
+    /// Makes this completion report the error number instead.
+    error: Option<(usize, i32)>,
+    /// Makes `complete` fail at this completion, as if the ring broke.
+    break_at: Option<usize>,
+    completed: usize,
+}
+
+#[cfg(test)]
+impl Faults {
+    /// Returns the result to report for a completion with result `res`, or
+    /// `None` if the ring should break instead.
+    fn apply(&mut self, res: i32) -> Option<i32> {
+        let index = self.completed;
+        self.completed += 1;
+        if self.break_at == Some(index) {
+            return None;
+        }
+        match self.error {
+            Some((at, errno)) if at == index => Some(-errno),
+            _ if res > 0 => Some(self.max_read.map_or(res, |max| res.min(max))),
+            _ => Some(res),
+        }
+    }
+}
+
+// SAFETY: the mappings belong to the ring alone and are only used through
+// `&mut self`.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Self> {
+        let Some((setup, _)) = SYSCALLS else {
+            return Err(io::ErrorKind::Unsupported.into());
+        };
+        let mut params = Params::default();
+        // SAFETY: `params` is a valid `struct io_uring_params` for the call
+        // to fill in.
+        let fd = unsafe { syscall(setup, entries as c_long, &mut params as *mut Params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` was just opened and is owned by nothing else.
+        let fd = unsafe { File::from_raw_fd(fd as i32) };
+        let raw = fd.as_raw_fd();
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * size_of::<Sqe>();
+        Ok(Self {
+            sq: Mapping::new(raw, sq_len, IORING_OFF_SQ_RING)?,
+            cq: Mapping::new(raw, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: Mapping::new(raw, sqes_len, IORING_OFF_SQES)?,
+            fd,
+            params,
+            #[cfg(test)]
+            faults: Faults::default(),
+        })
+    }
+
+    /// Queues a read of `iovec` from `fd` at `offset`, tagged `user_data`.
+    /// Callers keep fewer reads in flight than the ring has entries.
+    fn push_read(&mut self, fd: i32, offset: u64, iovec: *const IoVec, user_data: u64) {
+        let off = &self.params.sq_off;
+        let tail = self.sq.word(off.tail).load(Ordering::Relaxed);
+        let index = tail & self.sq.word(off.ring_mask).load(Ordering::Relaxed);
+        let sqe = Sqe {
+            opcode: IORING_OP_READV,
+            fd,
+            off: offset,
+            addr: iovec as usize as u64,
+            len: 1,
+            user_data,
+            ..Sqe::default()
+        };
+        // SAFETY: `index` is masked to the ring's entries, and the kernel
+        // doesn't read entries past the tail.
+        unsafe { self.sqes.ptr.cast::<Sqe>().add(index as usize).write(sqe) };
+        self.sq
+            .word(off.array + index * 4)
+            .store(index, Ordering::Relaxed);
+        self.sq
+            .word(off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Submits what is queued and waits for at least one completion, then
+    /// passes every completion to `f` as `(user_data, res)`.
+    fn complete(&mut self, mut f: impl FnMut(u64, i32)) -> io::Result<()> {
+        let (_, enter) = SYSCALLS.unwrap();
+        let (min_complete, no_sigset): (c_long, c_long) = (1, 0);
+        let off = &self.params.sq_off;
+        loop {
+            let queued = self
+                .sq
+                .word(off.tail)
+                .load(Ordering::Relaxed)
+                .wrapping_sub(self.sq.word(off.head).load(Ordering::Acquire));
+            // SAFETY: no signal mask is passed, and the queued entries point
+            // at buffers the caller keeps alive until they complete.
+            let result = unsafe {
+                syscall(
+                    enter,
+                    self.fd.as_raw_fd() as c_long,
+                    queued as c_long,
+                    min_complete,
+                    IORING_ENTER_GETEVENTS,
+                    std::ptr::null::<c_void>(),
+                    no_sigset,
+                )
+            };
+            if result >= 0 {
+                break;
+            }
+            // Interrupted, or short of resources for the moment.
+            let error = io::Error::last_os_error();
+            if !matches!(error.raw_os_error(), Some(EINTR | EAGAIN | EBUSY)) {
+                return Err(error);
+            }
+        }
+
+        let off = &self.params.cq_off;
+        let mask = self.cq.word(off.ring_mask).load(Ordering::Relaxed);
+        let mut head = self.cq.word(off.head).load(Ordering::Relaxed);
+        let tail = self.cq.word(off.tail).load(Ordering::Acquire);
+        while head != tail {
+            let index = (head & mask) as usize;
+            // SAFETY: `index` is masked to the ring's entries, and entries
+            // before the tail are complete.
+            let cqe = unsafe {
+                self.cq
+                    .ptr
+                    .add(off.cqes as usize)
+                    .cast::<Cqe>()
+                    .add(index)
+                    .read()
+            };
+            #[cfg(test)]
+            let cqe = match self.faults.apply(cqe.res) {
+                Some(res) => Cqe { res, ..cqe },
+                None => return Err(io::Error::other("injected ring failure")),
+            };
+            f(cqe.user_data, cqe.res);
+            head = head.wrapping_add(1);
+        }
+        self.cq.word(off.head).store(head, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// A page-aligned buffer, as `O_DIRECT` needs.
+struct Buffer {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// SAFETY: the buffer is plain memory owned by this value.
+unsafe impl Send for Buffer {}
+
+impl Buffer {
+    fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, DIRECT_ALIGN).unwrap();
+        // SAFETY: `size` is not zero.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        Self { ptr, layout }
+    }
+
+    fn as_slice(&self, len: usize) -> &[u8] {
+        assert!(len <= self.layout.size());
+        // SAFETY: in bounds of an initialized allocation that no read is
+        // writing to while it is looked at.
+        unsafe { std::slice::from_raw_parts(self.ptr, len) }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with this layout, and no read is in
+        // flight once the reader is done with it.
+        #[cfg(feature = "zeroize")]
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }.zeroize();
+        // SAFETY: as above.
+        unsafe { alloc::dealloc(self.ptr, self.layout) };
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Free,
+    /// Reading the buffer at `offset`, `filled` bytes so far.
+    Reading {
+        offset: u64,
+        filled: usize,
+    },
+    /// Read, waiting for the buffers before it to be passed on.
+    Done {
+        offset: u64,
+        filled: usize,
+    },
+}
+
+/// Reads whole files for hashing with io_uring, keeping several reads in
+/// flight in aligned buffers, and optionally with `O_DIRECT`.
+///
+/// The reads complete in any order but their data is passed on in file
+/// order, so the digests are the same as with ordinary reads. Where
+/// io_uring can't be set up (an old kernel, or one that disables it), files
+/// are read with ordinary reads instead; where `O_DIRECT` isn't supported,
+/// without it. [`uses_io_uring`](Self::uses_io_uring) tells which.
+///
+/// Available on Linux with the `io-uring` feature.
+pub struct UringReader {
+    // No read is left in flight between calls, so the buffers can go
+    // whenever the reader does.
+    ring: Option<Ring>,
+    buffers: Vec<Buffer>,
+    iovecs: Vec<IoVec>,
+    buffer_size: usize,
+    direct: bool,
+}
+
+impl UringReader {
+    /// Creates a reader with 8 buffers of 256 KiB.
+    pub fn new() -> Self {
+        Self::with_buffers(DEFAULT_BUFFERS, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Creates a reader keeping up to `count` reads of `size` bytes in
+    /// flight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero or `size` is not a nonzero multiple of
+    /// 4096.
+    pub fn with_buffers(count: usize, size: usize) -> Self {
+        assert!(count > 0, "a reader needs at least one buffer");
+        assert!(
+            size > 0 && size.is_multiple_of(DIRECT_ALIGN),
+            "buffer size must be a multiple of 4096"
+        );
+        let ring = u32::try_from(count)
+            .ok()
+            .and_then(|count| Ring::new(count).ok());
+        // Ordinary reads don't use the buffers.
+        let buffers: Vec<Buffer> = match ring {
+            Some(_) => (0..count).map(|_| Buffer::new(size)).collect(),
+            None => Vec::new(),
+        };
+        let iovecs = buffers
+            .iter()
+            .map(|buffer| IoVec {
+                base: buffer.ptr as usize,
+                len: size,
+            })
+            .collect();
+        Self {
+            ring,
+            buffers,
+            iovecs,
+            buffer_size: size,
+            direct: false,
+        }
+    }
+
+    /// Sets whether to open files with `O_DIRECT`, bypassing the page cache.
+    /// Off by default. Files on file systems that refuse it are read through
+    /// the cache anyway.
+    pub fn set_direct(&mut self, direct: bool) {
+        self.direct = direct;
+    }
+
+    /// Whether files are read with io_uring rather than ordinary reads.
+    pub fn uses_io_uring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Passes the contents of the file at `path` to `f` in order, in chunks,
+    /// and returns the number of bytes passed.
+    ///
+    /// Regular files and block devices are read up to the length they have
+    /// when opened; a file that shrinks meanwhile gives fewer bytes. Other
+    /// files, such as pipes, are read with ordinary reads until end of file.
+    pub fn read(&mut self, path: impl AsRef<Path>, mut f: impl FnMut(&[u8])) -> io::Result<u64> {
+        let path = path.as_ref();
+        let direct = O_DIRECT.filter(|_| self.direct && self.ring.is_some());
+        let (mut file, mut direct) = match direct {
+            Some(flag) => match OpenOptions::new().read(true).custom_flags(flag).open(path) {
+                Ok(file) => (file, true),
+                Err(e) if e.raw_os_error() == Some(EINVAL) => (File::open(path)?, false),
+                Err(e) => return Err(e),
+            },
+            None => (File::open(path)?, false),
+        };
+        let metadata = file.metadata()?;
+        let len = if metadata.is_file() {
+            metadata.len()
+        } else if metadata.file_type().is_block_device() {
+            (&file).seek(SeekFrom::End(0))?
+        } else {
+            0
+        };
+        if len == 0 {
+            return read_chunks(&file, |chunk| {
+                f(chunk);
+                Ok(())
+            });
+        }
+        if self.ring.is_none() {
+            return read_range(&file, 0, len, &mut f);
+        }
+
+        let mut offset = 0;
+        loop {
+            let result = read_ring(
+                self.ring.as_mut().unwrap(),
+                &self.buffers,
+                &mut self.iovecs,
+                self.buffer_size,
+                &file,
+                offset,
+                len,
+                &mut f,
+            );
+            match result {
+                Ok(read) => return Ok(read),
+                // Some file systems only refuse `O_DIRECT` when reading.
+                Err(ReadError::Read(read, e)) if direct && e.raw_os_error() == Some(EINVAL) => {
+                    file = File::open(path)?;
+                    direct = false;
+                    offset = read;
+                }
+                Err(ReadError::Read(_, e)) => return Err(e),
+                Err(ReadError::Ring(read)) => {
+                    // The kernel may still write to the buffers, so they
+                    // are never freed, and this file and later ones use
+                    // ordinary reads.
+                    self.ring = None;
+                    std::mem::forget(std::mem::take(&mut self.buffers));
+                    std::mem::forget(std::mem::take(&mut self.iovecs));
+                    if direct {
+                        file = File::open(path)?;
+                    }
+                    return read_range(&file, read, len, &mut f);
+                }
+            }
+        }
+    }
+
+    /// Computes the `kind` digest of the file at `path` and returns it with
+    /// the number of bytes read.
+    pub fn digest(
+        &mut self,
+        path: impl AsRef<Path>,
+        kind: DigestKind,
+    ) -> io::Result<(u64, TigerDigest)> {
+        match kind {
+            DigestKind::Tiger => {
+                let mut hasher = Tiger::new();
+                let read = self.read(path, |chunk| hasher.update(chunk))?;
+                Ok((read, hasher.finalize()))
+            }
+            DigestKind::Tree => {
+                let mut tree = TigerTree::new();
+                let read = self.read(path, |chunk| tree.update(chunk))?;
+                Ok((read, tree.finalize()))
+            }
+        }
+    }
+}
+
+impl Default for UringReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for UringReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringReader")
+            .field("uses_io_uring", &self.uses_io_uring())
+            .field("buffers", &self.buffers.len())
+            .field("buffer_size", &self.buffer_size)
+            .field("direct", &self.direct)
+            .finish()
+    }
+}
+
+enum ReadError {
+    /// A read failed at the offset given; no read is left in flight.
+    Read(u64, io::Error),
+    /// The ring itself failed, with reads possibly still in flight, after
+    /// the data up to the offset given was passed on.
+    Ring(u64),
+}
+
+/// Passes bytes `start..len` of `file` to `f` with ordinary reads, wherever
+/// the file position was, and returns the offset reached.
+fn read_range(file: &File, start: u64, len: u64, f: &mut impl FnMut(&[u8])) -> io::Result<u64> {
+    let mut file = file;
+    file.seek(SeekFrom::Start(start))?;
+    let read = read_chunks(file.take(len - start), |chunk| {
+        f(chunk);
+        Ok(())
+    })?;
+    Ok(start + read)
+}
+
+/// Reads bytes `start..len` of `file` through `ring`, one buffer per read,
+/// passing them to `f` in order. Returns the offset reached.
+#[allow(clippy::too_many_arguments)]
+fn read_ring(
+    ring: &mut Ring,
+    buffers: &[Buffer],
+    iovecs: &mut [IoVec],
+    size: usize,
+    file: &File,
+    start: u64,
+    len: u64,
+    f: &mut impl FnMut(&[u8]),
+) -> Result<u64, ReadError> {
+    let fd = file.as_raw_fd();
+    let expected = |offset: u64| (len - offset).min(size as u64) as usize;
+    let mut slots = vec![Slot::Free; buffers.len()];
+    let mut in_flight = 0;
+    let (mut next_read, mut delivered) = (start, start);
+    // Set once no more reads should start: at a short read or an error.
+    let mut stop = false;
+    let mut error = None;
+
+    loop {
+        // Pass on the buffers that are next in line.
+        while let Some(i) = slots
+            .iter()
+            .position(|slot| matches!(*slot, Slot::Done { offset, .. } if offset == delivered))
+        {
+            let Slot::Done { offset, filled } = slots[i] else {
+                unreachable!()
+            };
+            let wanted = expected(offset);
+            let filled = filled.min(wanted);
+            if error.is_none() {
+                f(buffers[i].as_slice(filled));
+                delivered += filled as u64;
+            }
+            slots[i] = Slot::Free;
+            if filled < wanted {
+                stop = true;
+            }
+        }
+
+        for (i, slot) in slots.iter_mut().enumerate() {
+            if stop || next_read >= len {
+                break;
+            }
+            if let Slot::Free = slot {
+                iovecs[i] = IoVec {
+                    base: buffers[i].ptr as usize,
+                    len: size,
+                };
+                ring.push_read(fd, next_read, &iovecs[i], i as u64);
+                *slot = Slot::Reading {
+                    offset: next_read,
+                    filled: 0,
+                };
+                next_read += size as u64;
+                in_flight += 1;
+            }
+        }
+
+        if in_flight == 0 {
+            return match error {
+                Some(e) => Err(ReadError::Read(delivered, e)),
+                None => Ok(delivered),
+            };
+        }
+
+        let mut failed = None;
+        let mut partial = Vec::new();
+        ring.complete(|user_data, res| {
+            let i = user_data as usize;
+            in_flight -= 1;
+            let Slot::Reading { offset, filled } = slots[i] else {
+                unreachable!("a completion for a buffer that isn't being read")
+            };
+            if res < 0 {
+                failed.get_or_insert(io::Error::from_raw_os_error(-res));
+                slots[i] = Slot::Free;
+                return;
+            }
+            let filled = filled + res as usize;
+            if res > 0 && filled < expected(offset) {
+                slots[i] = Slot::Reading { offset, filled };
+                partial.push(i);
+            } else {
+                slots[i] = Slot::Done { offset, filled };
+            }
+        })
+        .map_err(|_| ReadError::Ring(delivered))?;
+        if let Some(e) = failed {
+            stop = true;
+            error.get_or_insert(e);
+        }
+
+        // Short reads before the end continue where they stopped.
+        for i in partial {
+            let Slot::Reading { offset, filled } = slots[i] else {
+                unreachable!()
+            };
+            if stop {
+                slots[i] = Slot::Free;
+                continue;
+            }
+            iovecs[i] = IoVec {
+                base: buffers[i].ptr as usize + filled,
+                len: size - filled,
+            };
+            ring.push_read(fd, offset + filled as u64, &iovecs[i], i as u64);
+            in_flight += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::{digest, tree_hash};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn check(reader: &mut UringReader, path: &Path, contents: &[u8]) {
+        let mut read = Vec::new();
+        let len = reader
+            .read(path, |chunk| read.extend_from_slice(chunk))
+            .unwrap();
+        assert_eq!(len, contents.len() as u64, "{:?}", reader);
+        assert!(read == contents, "{:?}", reader);
+        let tiger = reader.digest(path, DigestKind::Tiger).unwrap();
+        assert_eq!(tiger, (len, digest(contents)));
+        let tree = reader.digest(path, DigestKind::Tree).unwrap();
+        assert_eq!(tree, (len, tree_hash(contents)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't make system calls")]
+    fn test_read() {
+        let root = temp_dir("read");
+        let contents: Vec<u8> = (0..1_300_001u32)
+            .map(|i| (i * 13 + i / 509) as u8)
+            .collect();
+        fs::write(root.join("file"), &contents).unwrap();
+        fs::write(root.join("empty"), b"").unwrap();
+
+        for (count, size) in [(1, 4096), (3, 8192), (8, 256 * 1024), (4, 4 << 20)] {
+            for direct in [false, true] {
+                let mut reader = UringReader::with_buffers(count, size);
+                let buffers = if reader.uses_io_uring() { count } else { 0 };
+                assert_eq!(reader.buffers.len(), buffers);
+                reader.set_direct(direct);
+                check(&mut reader, &root.join("file"), &contents);
+                check(&mut reader, &root.join("empty"), b"");
+            }
+        }
+
+        let mut reader = UringReader::new();
+        let error = reader.read(root.join("missing"), |_| ()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        // Still usable after an error.
+        check(&mut reader, &root.join("file"), &contents);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_fallback() {
+        let root = temp_dir("fallback");
+        let contents = vec![0xA7u8; 100_000];
+        fs::write(root.join("file"), &contents).unwrap();
+        let mut reader = UringReader {
+            ring: None,
+            ..UringReader::with_buffers(2, 4096)
+        };
+        reader.set_direct(true);
+        assert!(!reader.uses_io_uring());
+        check(&mut reader, &root.join("file"), &contents);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    /// A reader whose ring fails as `faults` say, or `None` where io_uring
+    /// can't be set up.
+    fn faulty_reader(count: usize, size: usize, faults: Faults) -> Option<UringReader> {
+        let mut reader = UringReader::with_buffers(count, size);
+        reader.ring.as_mut()?.faults = faults;
+        Some(reader)
+    }
+
+    fn sample(root: &Path) -> (PathBuf, Vec<u8>) {
+        let contents: Vec<u8> = (0..100_003u32).map(|i| (i * 11 + i / 251) as u8).collect();
+        let path = root.join("file");
+        fs::write(&path, &contents).unwrap();
+        (path, contents)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't make system calls")]
+    fn test_ring_failure() {
+        let root = temp_dir("ring-failure");
+        let (path, contents) = sample(&root);
+        // Before any data, after some, and with reads done out of order.
+        for break_at in [0, 1, 5, 9] {
+            let faults = Faults {
+                break_at: Some(break_at),
+                ..Faults::default()
+            };
+            let Some(mut reader) = faulty_reader(4, 8192, faults) else {
+                return;
+            };
+            let (read, digest) = reader.digest(&path, DigestKind::Tiger).unwrap();
+            assert_eq!(
+                (read, digest),
+                (contents.len() as u64, crate::digest(&contents))
+            );
+            // Later files are read with ordinary reads.
+            assert!(!reader.uses_io_uring());
+            check(&mut reader, &path, &contents);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't make system calls")]
+    fn test_short_reads() {
+        let root = temp_dir("short-reads");
+        let (path, contents) = sample(&root);
+        for max_read in [1, 1000, 4096] {
+            let faults = Faults {
+                max_read: Some(max_read),
+                ..Faults::default()
+            };
+            let Some(mut reader) = faulty_reader(3, 8192, faults) else {
+                return;
+            };
+            check(&mut reader, &path, &contents);
+            assert!(reader.uses_io_uring());
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't make system calls")]
+    fn test_read_errors() {
+        const EIO: i32 = 5;
+
+        let root = temp_dir("read-errors");
+        let (path, contents) = sample(&root);
+        let faults = Faults {
+            error: Some((3, EIO)),
+            ..Faults::default()
+        };
+        let Some(mut reader) = faulty_reader(4, 8192, faults) else {
+            return;
+        };
+        let error = reader.read(&path, |_| ()).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(EIO));
+        // Nothing is left in flight, so the ring can be used again.
+        assert!(reader.uses_io_uring());
+        check(&mut reader, &path, &contents);
+
+        // A read refusing `O_DIRECT` is retried from where it stopped
+        // without it, where the file system took it when opening.
+        let Some(flag) = O_DIRECT else {
+            return;
+        };
+        if OpenOptions::new()
+            .read(true)
+            .custom_flags(flag)
+            .open(&path)
+            .is_err()
+        {
+            return;
+        }
+        for at in [0, 3, 10] {
+            let faults = Faults {
+                error: Some((at, EINVAL)),
+                ..Faults::default()
+            };
+            let mut reader = faulty_reader(4, 8192, faults).unwrap();
+            reader.set_direct(true);
+            check(&mut reader, &path, &contents);
+            assert!(reader.uses_io_uring());
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri isolates the file system")]
+    fn test_read_range() {
+        let root = temp_dir("range");
+        let contents: Vec<u8> = (0..20_000u32).map(|i| (i * 7) as u8).collect();
+        fs::write(root.join("file"), &contents).unwrap();
+        let mut file = File::open(root.join("file")).unwrap();
+        // Left at the end, as finding the length of a block device does.
+        file.seek(SeekFrom::End(0)).unwrap();
+        for start in [0, 5000] {
+            let mut read = Vec::new();
+            let end = read_range(&file, start, 15_000, &mut |chunk: &[u8]| {
+                read.extend_from_slice(chunk)
+            })
+            .unwrap();
+            assert_eq!(end, 15_000);
+            assert!(read == contents[start as usize..15_000]);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "multiple of 4096")]
+    fn test_unaligned_buffers() {
+        UringReader::with_buffers(2, 1000);
+    }
+}
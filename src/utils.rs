@@ -1,136 +1,330 @@
-use byteorder::{ByteOrder, LittleEndian};
-use std::num::Wrapping;
-
 pub const TIGER_PASSES: usize = 3;
 
-const WRAPPED_FF: Wrapping<u64> = Wrapping(0xFF);
+pub const START_VALUES: [u64; 3] = [0x0123456789ABCDEF, 0xFEDCBA9876543210, 0xF096A5B4C3B2E187];
+
+/// The string the standard S-boxes are generated from.
+pub const SBOX_SEED: &[u8; 64] =
+    b"Tiger - A Fast New Hash Function, by Ross Anderson and Eli Biham";
 
-const IS_LITTLE_ENDIAN: bool = cfg!(target_endian = "little");
+/// Number of generation passes used for the standard S-boxes.
+pub const SBOX_GENERATION_PASSES: usize = 5;
 
-pub const START_VALUES: [u64; 3] = [0x0123456789ABCDEF, 0xFEDCBA9876543210, 0xF096A5B4C3B2E187];
+/// Fingerprints of the reference tables t1-t4 from the Tiger paper, as
+/// computed by [`sbox_fingerprint`].
+///
+/// These were computed from the published tables, transcribed independently
+/// of [`generate_sboxes`], and never from the tables this crate generates;
+/// regenerating them from `SBOXES` would make the check pointless.
+pub const SBOX_FINGERPRINTS: [[u64; 4]; 4] = [
+    [
+        0x02AAB17CF7E90C5E,
+        0xEBC18760ED78A77A,
+        0xE19A41B8BA514B12,
+        0xB44A75526F8786FF,
+    ],
+    [
+        0xE6A6BE5A05A12138,
+        0x22FAC097AA8D5C0E,
+        0xAEC967A4337E1820,
+        0x40EC174FE207938B,
+    ],
+    [
+        0xF49FCC2FF1DAF39B,
+        0x6D0E60F5C3578A9E,
+        0xAAC7B33A68D6EBA5,
+        0xB575CFB317D34D1B,
+    ],
+    [
+        0x5B0E608526323C55,
+        0xC3A0396F7363A51F,
+        0xA4016658FC9C055D,
+        0x45AE91FBC56EDA09,
+    ],
+];
 
-pub fn compress_with_sbox(char: [u64; 8], state: &mut [u64; 3], sboxes: [[u8; 8]; 1024]) {
-    let (mut a, mut b, mut c) = (Wrapping(state[0]), Wrapping(state[1]), Wrapping(state[2]));
-    let (aa, bb, cc) = (a, b, c);
-    let (mut x0, mut x1, mut x2, mut x3, mut x4, mut x5, mut x6, mut x7) = (
-        Wrapping(char[0]),
-        Wrapping(char[1]),
-        Wrapping(char[2]),
-        Wrapping(char[3]),
-        Wrapping(char[4]),
-        Wrapping(char[5]),
-        Wrapping(char[6]),
-        Wrapping(char[7]),
-    );
-
-    let sb1 = &sboxes[0..256].try_into().unwrap();
-    let sb2 = &sboxes[256..512].try_into().unwrap();
-    let sb3 = &sboxes[512..768].try_into().unwrap();
-    let sb4 = &sboxes[768..1024].try_into().unwrap();
-
-    for pass_no in 0..TIGER_PASSES {
-        if pass_no != 0 {
-            x0 -= x7 ^ Wrapping(0xA5A5A5A5A5A5A5A5);
-            x1 ^= x0;
-            x2 += x1;
-            x3 -= x2 ^ ((!x1) << 19);
-            x4 ^= x3;
-            x5 += x4;
-            x6 -= x5 ^ ((!x4) >> 23);
-            x7 ^= x6;
-            x0 += x7;
-            x1 -= x0 ^ ((!x7) << 19);
-            x2 ^= x1;
-            x3 += x2;
-            x4 -= x3 ^ ((!x2) >> 23);
-            x5 ^= x4;
-            x6 += x5;
-            x7 -= x6 ^ Wrapping(0x0123456789ABCDEF);
+/// Fingerprint of a single 256-entry table: its first and last entries, a
+/// position-weighted sum and a position-rotated xor of all entries. Both
+/// aggregates change if any two entries are swapped.
+pub const fn sbox_fingerprint(table: &[[u8; 8]]) -> [u64; 4] {
+    let (mut sum, mut xor) = (0u64, 0u64);
+    let mut i = 0;
+    while i < table.len() {
+        let entry = read_u64(&table[i]);
+        sum = sum.wrapping_add(entry.wrapping_mul(i as u64 + 1));
+        xor ^= entry.rotate_left(i as u32 % 64);
+        i += 1;
+    }
+    [
+        read_u64(&table[0]),
+        read_u64(&table[table.len() - 1]),
+        sum,
+        xor,
+    ]
+}
+
+/// Returns the index (0 for t1, ..., 3 for t4) of the first table whose
+/// fingerprint differs from the reference tables.
+pub const fn find_sbox_mismatch(sboxes: &[[u8; 8]; 1024]) -> Option<usize> {
+    let mut rest: &[[u8; 8]] = sboxes;
+    let mut table = 0;
+    while let Some((entries, next)) = rest.split_first_chunk::<256>() {
+        let fingerprint = sbox_fingerprint(entries);
+        let mut i = 0;
+        while i < 4 {
+            if fingerprint[i] != SBOX_FINGERPRINTS[table][i] {
+                return Some(table);
+            }
+            i += 1;
         }
-        let mul = Wrapping(if pass_no == 0 {
-            5
-        } else {
-            if pass_no == 1 {
-                7
-            } else {
-                9
+        rest = next;
+        table += 1;
+    }
+    None
+}
+
+/// The four Tiger S-boxes t1-t4 as 64-bit entries.
+///
+/// Each table is 2 KiB and the struct is aligned to a cache line, so every
+/// table starts on a cache-line boundary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub struct SboxTables(pub [[u64; 256]; 4]);
+
+impl SboxTables {
+    /// Converts byte-oriented S-boxes, laid out like `SBOXES`.
+    pub const fn from_sboxes(sboxes: &[[u8; 8]; 1024]) -> Self {
+        let mut tables = [[0u64; 256]; 4];
+        let mut i = 0;
+        while i < 1024 {
+            tables[i / 256][i % 256] = read_u64(&sboxes[i]);
+            i += 1;
+        }
+        Self(tables)
+    }
+
+    /// Converts back to byte-oriented S-boxes.
+    pub const fn to_sboxes(&self) -> [[u8; 8]; 1024] {
+        let mut sboxes = [[0u8; 8]; 1024];
+        let mut i = 0;
+        while i < 1024 {
+            sboxes[i] = write_u64(self.0[i / 256][i % 256]);
+            i += 1;
+        }
+        sboxes
+    }
+}
+
+/// Generates Tiger S-boxes the way the Tiger paper does.
+///
+/// Starting from the identity tables, every entry is swapped with one chosen by
+/// the chaining state, and the state is advanced by compressing `seed` with
+/// the tables generated so far. `generate_sboxes(SBOX_SEED, 5)` gives the
+/// standard tables.
+pub const fn generate_sboxes(seed: &[u8; 64], passes: usize) -> [[u8; 8]; 1024] {
+    generate_sbox_tables(seed, passes).to_sboxes()
+}
+
+/// Same as [`generate_sboxes`], returning the tables in 64-bit form.
+pub const fn generate_sbox_tables(seed: &[u8; 64], passes: usize) -> SboxTables {
+    let start = read_block(seed);
+    let mut state: [u64; 3] = START_VALUES;
+
+    // Entry `i` of every identity table holds the byte `i` in all 8 columns.
+    let mut identity = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        identity[i] = i as u64 * 0x0101010101010101;
+        i += 1;
+    }
+    let mut tables = SboxTables([identity; 4]);
+
+    let mut abc = 2;
+
+    let mut pass = 0;
+    while pass < passes {
+        let mut i = 0;
+        while i < 256 {
+            let mut sb = 0;
+            while sb < 4 {
+                abc += 1;
+                if abc >= 3 {
+                    abc = 0;
+                    compress_with_sbox::<TIGER_PASSES>(&start, &mut state, &tables);
+                }
+                let table = &mut tables.0[sb];
+                let mut shift = 0;
+                while shift < 64 {
+                    let mask = 0xFF << shift;
+                    let j = ((state[abc] >> shift) & 0xFF) as usize;
+                    let (val_i, val_j) = (table[i] & mask, table[j] & mask);
+                    table[i] = (table[i] & !mask) | val_j;
+                    table[j] = (table[j] & !mask) | val_i;
+                    shift += 8;
+                }
+                sb += 1;
             }
-        });
-        round(&mut a, &mut b, &mut c, x0, mul, sb1, sb2, sb3, sb4);
-        round(&mut b, &mut c, &mut a, x1, mul, sb1, sb2, sb3, sb4);
-        round(&mut c, &mut a, &mut b, x2, mul, sb1, sb2, sb3, sb4);
-        round(&mut a, &mut b, &mut c, x3, mul, sb1, sb2, sb3, sb4);
-        round(&mut b, &mut c, &mut a, x4, mul, sb1, sb2, sb3, sb4);
-        round(&mut c, &mut a, &mut b, x5, mul, sb1, sb2, sb3, sb4);
-        round(&mut a, &mut b, &mut c, x6, mul, sb1, sb2, sb3, sb4);
-        round(&mut b, &mut c, &mut a, x7, mul, sb1, sb2, sb3, sb4);
-
-        (a, b, c) = (c, a, b);
+            i += 1;
+        }
+        pass += 1;
     }
 
-    a ^= aa;
-    b -= bb;
-    c += cc;
+    tables
+}
 
-    (state[0], state[1], state[2]) = (a.0, b.0, c.0);
+/// Multiplier used by pass `pass_no`: 5, 7 and 9 for the three standard passes,
+/// and 9 for every extra pass after those.
+pub const fn pass_multiplier(pass_no: usize) -> u64 {
+    match pass_no {
+        0 => 5,
+        1 => 7,
+        _ => 9,
+    }
 }
 
-fn round(
-    a: &mut Wrapping<u64>,
-    b: &mut Wrapping<u64>,
-    c: &mut Wrapping<u64>,
-    x: Wrapping<u64>,
-    mul: Wrapping<u64>,
-    sb1: &[[u8; 8]; 256],
-    sb2: &[[u8; 8]; 256],
-    sb3: &[[u8; 8]; 256],
-    sb4: &[[u8; 8]; 256],
+/// The passes of the compression function, shared by every backend. `$pass`
+/// and `$key_schedule` are the backend's own and are applied to the words
+/// `$a`, `$b` and `$c` and the expanded block `$x`; `$tables` is passed on to
+/// `$pass`. Afterwards the words are in order for the feedforward. Usable in
+/// a `const fn`.
+macro_rules! passes {
+    (
+        $passes:ident, [$a:ident, $b:ident, $c:ident], $x:ident,
+        $pass:path, $key_schedule:path, $tables:expr
+    ) => {{
+        use $crate::utils::pass_multiplier;
+
+        const { assert!($passes >= 3, "Tiger needs at least three passes") };
+
+        $pass(&mut $a, &mut $b, &mut $c, &$x, pass_multiplier(0), $tables);
+        $key_schedule(&mut $x);
+        $pass(&mut $c, &mut $a, &mut $b, &$x, pass_multiplier(1), $tables);
+        $key_schedule(&mut $x);
+        $pass(&mut $b, &mut $c, &mut $a, &$x, pass_multiplier(2), $tables);
+
+        let mut pass_no = 3;
+        while pass_no < $passes {
+            let mul = pass_multiplier(pass_no);
+            $key_schedule(&mut $x);
+            $pass(&mut $a, &mut $b, &mut $c, &$x, mul, $tables);
+            ($a, $b, $c) = ($c, $a, $b);
+            pass_no += 1;
+        }
+    }};
+}
+// Not every target compiles a backend besides the one below.
+#[allow(unused_imports)]
+pub(crate) use passes;
+
+/// The Tiger compression function with `PASSES` passes (at least 3).
+///
+/// This is a `const fn` so digests can also be computed at compile time. The
+/// other backends in [`compress`](crate::compress) must give the same result.
+pub const fn compress_with_sbox<const PASSES: usize>(
+    block: &[u64; 8],
+    state: &mut [u64; 3],
+    tables: &SboxTables,
 ) {
+    let [mut a, mut b, mut c] = *state;
+    let mut x = *block;
+    passes!(PASSES, [a, b, c], x, pass, key_schedule, tables);
+
+    *state = [
+        a ^ state[0],
+        b.wrapping_sub(state[1]),
+        c.wrapping_add(state[2]),
+    ];
+}
+
+#[inline(always)]
+pub const fn key_schedule(x: &mut [u64; 8]) {
+    x[0] = x[0].wrapping_sub(x[7] ^ 0xA5A5A5A5A5A5A5A5);
+    x[1] ^= x[0];
+    x[2] = x[2].wrapping_add(x[1]);
+    x[3] = x[3].wrapping_sub(x[2] ^ (!x[1] << 19));
+    x[4] ^= x[3];
+    x[5] = x[5].wrapping_add(x[4]);
+    x[6] = x[6].wrapping_sub(x[5] ^ (!x[4] >> 23));
+    x[7] ^= x[6];
+    x[0] = x[0].wrapping_add(x[7]);
+    x[1] = x[1].wrapping_sub(x[0] ^ (!x[7] << 19));
+    x[2] ^= x[1];
+    x[3] = x[3].wrapping_add(x[2]);
+    x[4] = x[4].wrapping_sub(x[3] ^ (!x[2] >> 23));
+    x[5] ^= x[4];
+    x[6] = x[6].wrapping_add(x[5]);
+    x[7] = x[7].wrapping_sub(x[6] ^ 0x0123456789ABCDEF);
+}
+
+#[inline(always)]
+const fn pass(a: &mut u64, b: &mut u64, c: &mut u64, x: &[u64; 8], mul: u64, tables: &SboxTables) {
+    round(a, b, c, x[0], mul, tables);
+    round(b, c, a, x[1], mul, tables);
+    round(c, a, b, x[2], mul, tables);
+    round(a, b, c, x[3], mul, tables);
+    round(b, c, a, x[4], mul, tables);
+    round(c, a, b, x[5], mul, tables);
+    round(a, b, c, x[6], mul, tables);
+    round(b, c, a, x[7], mul, tables);
+}
+
+#[inline(always)]
+const fn round(a: &mut u64, b: &mut u64, c: &mut u64, x: u64, mul: u64, tables: &SboxTables) {
+    let [t1, t2, t3, t4] = &tables.0;
     *c ^= x;
-    *a -= read_u64(&sb1[((*c >> (0 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb2[((*c >> (2 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb3[((*c >> (4 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb4[((*c >> (6 * 8)) & WRAPPED_FF).0 as usize]);
-    *b += read_u64(&sb4[((*c >> (1 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb3[((*c >> (3 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb2[((*c >> (5 * 8)) & WRAPPED_FF).0 as usize])
-        ^ read_u64(&sb1[((*c >> (7 * 8)) & WRAPPED_FF).0 as usize]);
-    *b *= mul;
-}
-
-fn read_u64(bytes: &[u8]) -> u64 {
-    <byteorder::LittleEndian as byteorder::ByteOrder>::read_u64(bytes)
-}
-
-pub fn readChunks(bytes: [u8; 64]) -> [u64; 8] {
-    (0..8)
-        .map(|i| {
-            let mut slice = if !IS_LITTLE_ENDIAN {
-                bytes[63 - (i + 1) * 8..63 - i * 8].to_vec()
-            } else {
-                bytes[i * 8..(i + 1) * 8].to_vec()
-            };
-            if !IS_LITTLE_ENDIAN {
-                slice.reverse()
-            };
-            LittleEndian::read_u64(&slice)
-        })
-        .collect::<Vec<u64>>()
-        .try_into()
-        .unwrap()
-}
-
-pub fn writeChunks(chunks: [u64; 8]) -> [u8; 64] {
-    let mut bytes = [0u8; 64];
-    for (i, &chunk) in chunks.iter().enumerate() {
-        let start = i * 8;
-        let end = start + 8;
-        if !IS_LITTLE_ENDIAN {
-            LittleEndian::write_u64_into(&[chunk], &mut bytes[start..end]);
-            bytes.reverse();
-        } else {
-            LittleEndian::write_u64_into(&[chunk], &mut bytes[start..end]);
-        }
+    let i = write_u64(*c);
+    let even = t1[i[0] as usize] ^ t2[i[2] as usize] ^ t3[i[4] as usize] ^ t4[i[6] as usize];
+    let odd = t4[i[1] as usize] ^ t3[i[3] as usize] ^ t2[i[5] as usize] ^ t1[i[7] as usize];
+    *a = a.wrapping_sub(even);
+    *b = b.wrapping_add(odd).wrapping_mul(mul);
+}
+
+// Tiger is defined on little-endian words. All conversions between bytes and
+// words go through these helpers, which never depend on the target's byte
+// order.
+
+/// Reads a little-endian word.
+pub const fn read_u64(bytes: &[u8; 8]) -> u64 {
+    u64::from_le_bytes(*bytes)
+}
+
+/// Writes a word as little-endian bytes.
+pub const fn write_u64(word: u64) -> [u8; 8] {
+    word.to_le_bytes()
+}
+
+/// Reads `words.len()` little-endian words from the start of `bytes`.
+pub fn read_words(bytes: &[u8], words: &mut [u64]) {
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(8)) {
+        *word = read_u64(chunk.try_into().unwrap());
+    }
+}
+
+/// Writes `words` as little-endian bytes to the start of `bytes`.
+pub fn write_words(words: &[u64], bytes: &mut [u8]) {
+    for (chunk, &word) in bytes.chunks_exact_mut(8).zip(words) {
+        chunk.copy_from_slice(&write_u64(word));
     }
-    bytes
+}
+
+/// Reads a 64-byte block as eight little-endian words.
+pub const fn read_block(block: &[u8; 64]) -> [u64; 8] {
+    let mut words = [0u64; 8];
+    let mut i = 0;
+    while i < 8 {
+        let (_, rest) = block.split_at(i * 8);
+        let Some(word) = rest.first_chunk() else {
+            unreachable!()
+        };
+        words[i] = read_u64(word);
+        i += 1;
+    }
+    words
+}
+
+/// Writes eight words as a 64-byte little-endian block.
+#[cfg(test)]
+pub fn write_block(words: &[u64; 8]) -> [u8; 64] {
+    let mut block = [0u8; 64];
+    write_words(words, &mut block);
+    block
 }
@@ -0,0 +1,571 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::{DigestKind, TigerDigest};
+
+/// How long a file has to stay quiet before it is rehashed, by default.
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+const IN_CLOEXEC: i32 = 0o2000000;
+const IN_MODIFY: u32 = 0x2;
+const IN_CLOSE_WRITE: u32 = 0x8;
+const IN_MOVED_FROM: u32 = 0x40;
+const IN_MOVED_TO: u32 = 0x80;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+const IN_DELETE_SELF: u32 = 0x400;
+const IN_MOVE_SELF: u32 = 0x800;
+const IN_Q_OVERFLOW: u32 = 0x4000;
+const IN_IGNORED: u32 = 0x8000;
+const IN_ONLYDIR: u32 = 0x0100_0000;
+const IN_DONT_FOLLOW: u32 = 0x0200_0000;
+const IN_EXCL_UNLINK: u32 = 0x0400_0000;
+
+const WATCH_MASK: u32 = IN_MODIFY
+    | IN_CLOSE_WRITE
+    | IN_MOVED_FROM
+    | IN_MOVED_TO
+    | IN_CREATE
+    | IN_DELETE
+    | IN_DELETE_SELF
+    | IN_MOVE_SELF
+    | IN_ONLYDIR
+    | IN_DONT_FOLLOW
+    | IN_EXCL_UNLINK;
+
+/// Size of `struct inotify_event` without the name.
+const EVENT_HEADER_LEN: usize = 16;
+
+const POLLIN: i16 = 1;
+
+const ENOSPC: i32 = 28;
+
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+extern "C" {
+    fn inotify_init1(flags: i32) -> i32;
+    fn inotify_add_watch(fd: i32, path: *const std::ffi::c_char, mask: u32) -> i32;
+    fn inotify_rm_watch(fd: i32, wd: i32) -> i32;
+    fn poll(fds: *mut PollFd, nfds: std::ffi::c_ulong, timeout: i32) -> i32;
+}
+
+/// A file whose contents changed, as reported by [`Watcher::next_changes`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub path: PathBuf,
+    /// Digest before the change, `None` if the file is new.
+    pub old: Option<TigerDigest>,
+    /// Digest after the change, `None` if the file is gone.
+    pub new: Option<TigerDigest>,
+}
+
+/// Watches a directory tree with inotify and reports files whose contents
+/// change.
+///
+/// Creating the watcher hashes every regular file in the tree. After that,
+/// only files that inotify reports as touched are read again. Writes are
+/// debounced: a file is rehashed once it has had no events for the debounce
+/// delay, so a burst of writes gives a single change, and a file that keeps
+/// being written doesn't hold up changes to other files. Symbolic links are
+/// neither followed nor reported, and a file whose digest comes out the same
+/// as before is not reported either.
+///
+/// When the kernel's event queue overflows, the whole tree is compared with
+/// the digests known so far, so no change is lost, only delayed.
+#[derive(Debug)]
+pub struct Watcher {
+    inotify: File,
+    root: PathBuf,
+    kind: DigestKind,
+    debounce: Duration,
+    /// Watched directories by watch descriptor.
+    watches: HashMap<i32, PathBuf>,
+    digests: BTreeMap<PathBuf, TigerDigest>,
+    /// Paths with events not dealt with yet, and when the last one came.
+    pending: BTreeMap<PathBuf, Instant>,
+}
+
+impl Watcher {
+    /// Starts watching the directory `root`, computing digests of `kind`.
+    ///
+    /// Files and directories that can't be read or watched are passed to
+    /// `on_error` and left out until they change.
+    pub fn new(
+        root: impl AsRef<Path>,
+        kind: DigestKind,
+        mut on_error: impl FnMut(&Path, io::Error),
+    ) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        if !fs::metadata(&root)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: not a directory", root.display()),
+            ));
+        }
+        // SAFETY: inotify_init1 only takes flags.
+        let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut watcher = Self {
+            // SAFETY: `fd` was just opened and is owned by nothing else.
+            inotify: unsafe { File::from_raw_fd(fd) },
+            root: root.clone(),
+            kind,
+            debounce: DEFAULT_DEBOUNCE,
+            watches: HashMap::new(),
+            digests: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        };
+        watcher.sync(&root, &mut on_error);
+        Ok(watcher)
+    }
+
+    /// Sets how long a file must go without events before it is rehashed.
+    /// The default is half a second.
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    /// The files currently known and their digests, sorted by path.
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &TigerDigest)> {
+        self.digests
+            .iter()
+            .map(|(path, digest)| (path.as_path(), digest))
+    }
+
+    /// Waits for files to change and returns the changes, sorted by path.
+    ///
+    /// Returns an empty list if `timeout` passes without any change; with
+    /// `None` this waits as long as it takes. Files that can't be read are
+    /// passed to `on_error` and keep their old digest.
+    pub fn next_changes(
+        &mut self,
+        timeout: Option<Duration>,
+        mut on_error: impl FnMut(&Path, io::Error),
+    ) -> io::Result<Vec<ChangeEvent>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let now = Instant::now();
+            let quiet: BTreeSet<PathBuf> = self
+                .pending
+                .iter()
+                .filter(|&(_, &last)| now.duration_since(last) >= self.debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+            if !quiet.is_empty() {
+                self.pending.retain(|path, _| !quiet.contains(path));
+                let mut changes = Vec::new();
+                for path in minimal_paths(quiet) {
+                    changes.extend(self.sync(&path, &mut on_error));
+                }
+                changes.sort_by(|a, b| a.path.cmp(&b.path));
+                if !changes.is_empty() {
+                    return Ok(changes);
+                }
+            }
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Ok(Vec::new());
+            }
+
+            // Wake up for the next event, when the next path has been quiet
+            // long enough, or at the deadline, whichever comes first.
+            let settled = self
+                .pending
+                .values()
+                .min()
+                .map(|&last| last + self.debounce);
+            let wake = match (settled, deadline) {
+                (Some(settled), Some(deadline)) => Some(settled.min(deadline)),
+                (wake, None) | (None, wake) => wake,
+            };
+            if self.wait_readable(wake.map(|wake| wake.saturating_duration_since(now)))? {
+                self.read_events()?;
+            }
+        }
+    }
+
+    /// Waits until inotify has events to read, for at most `timeout`.
+    fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let timeout = timeout.map_or(-1, |timeout| {
+            // Round up so short waits don't spin.
+            timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+        });
+        let mut fd = PollFd {
+            fd: self.inotify.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        loop {
+            // SAFETY: `fd` is a valid pollfd and the count is 1.
+            match unsafe { poll(&mut fd, 1, timeout) } {
+                0 => return Ok(false),
+                ready if ready > 0 => return Ok(true),
+                _ => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the queued events and marks the paths they concern as pending.
+    fn read_events(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; 64 * 1024];
+        let read = loop {
+            match self.inotify.read(&mut buffer) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => break result?,
+            }
+        };
+        let now = Instant::now();
+        let mut events = &buffer[..read];
+        while events.len() >= EVENT_HEADER_LEN {
+            let field = |at: usize| u32::from_ne_bytes(events[at..at + 4].try_into().unwrap());
+            let (wd, mask, name_len) = (field(0) as i32, field(4), field(12) as usize);
+            let name = &events[EVENT_HEADER_LEN..EVENT_HEADER_LEN + name_len];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            events = &events[EVENT_HEADER_LEN + name_len..];
+
+            if mask & IN_Q_OVERFLOW != 0 {
+                self.pending.insert(self.root.clone(), now);
+                continue;
+            }
+            let Some(dir) = self.watches.get(&wd) else {
+                continue;
+            };
+            if mask & IN_IGNORED != 0 {
+                self.watches.remove(&wd);
+            } else if mask & (IN_DELETE_SELF | IN_MOVE_SELF) != 0 {
+                self.pending.insert(dir.clone(), now);
+            } else if !name.is_empty() {
+                self.pending.insert(dir.join(OsStr::from_bytes(name)), now);
+            }
+        }
+        Ok(())
+    }
+
+    /// Brings the digests of everything at or below `path` up to date with
+    /// the file system and returns the changes. Files still pending are left
+    /// for when they have been quiet long enough.
+    fn sync(
+        &mut self,
+        path: &Path,
+        on_error: &mut dyn FnMut(&Path, io::Error),
+    ) -> Vec<ChangeEvent> {
+        let mut found = BTreeSet::new();
+        let mut watched = HashSet::new();
+        self.scan(path, &mut found, &mut watched, on_error);
+
+        // Directories that were at or below `path` and weren't found there
+        // again were deleted or moved out of the tree.
+        let stale: Vec<i32> = self
+            .watches
+            .iter()
+            .filter(|&(wd, dir)| dir.starts_with(path) && !watched.contains(wd))
+            .map(|(&wd, _)| wd)
+            .collect();
+        for wd in stale {
+            self.watches.remove(&wd);
+            // SAFETY: inotify_rm_watch only takes plain values. It fails
+            // harmlessly for a watch the kernel already dropped.
+            unsafe { inotify_rm_watch(self.inotify.as_raw_fd(), wd) };
+        }
+
+        let known: Vec<PathBuf> = self
+            .digests
+            .range(path.to_path_buf()..)
+            .map(|(known, _)| known)
+            .take_while(|known| known.starts_with(path))
+            .cloned()
+            .collect();
+        let mut changes = Vec::new();
+        for gone in known.into_iter().filter(|known| !found.contains(known)) {
+            let old = self.digests.remove(&gone);
+            changes.push(ChangeEvent {
+                path: gone,
+                old,
+                new: None,
+            });
+        }
+        for file in found {
+            if self.pending.contains_key(&file) {
+                continue;
+            }
+            let new = match File::open(&file).and_then(|file| self.kind.digest_file(&file)) {
+                Ok((_, digest)) => digest,
+                // Gone again since the scan; the next event deals with it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    on_error(&file, e);
+                    continue;
+                }
+            };
+            let old = self.digests.insert(file.clone(), new);
+            if old != Some(new) {
+                changes.push(ChangeEvent {
+                    path: file,
+                    old,
+                    new: Some(new),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Adds the regular files at or below `path` to `found`, watching every
+    /// directory on the way and adding its watch descriptor to `watched`.
+    fn scan(
+        &mut self,
+        path: &Path,
+        found: &mut BTreeSet<PathBuf>,
+        watched: &mut HashSet<i32>,
+        on_error: &mut dyn FnMut(&Path, io::Error),
+    ) {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => return on_error(path, e),
+        };
+        if metadata.is_file() {
+            found.insert(path.to_path_buf());
+            return;
+        }
+        if !metadata.is_dir() {
+            return;
+        }
+        // Watch before listing, so files created in between aren't missed.
+        match self.add_watch(path) {
+            Ok(wd) => watched.insert(wd),
+            Err(e) => return on_error(path, e),
+        };
+        let entries = match fs::read_dir(path).and_then(|dir| dir.collect::<io::Result<Vec<_>>>()) {
+            Ok(entries) => entries,
+            Err(e) => return on_error(path, e),
+        };
+        for entry in entries {
+            self.scan(&entry.path(), found, watched, on_error);
+        }
+    }
+
+    fn add_watch(&mut self, dir: &Path) -> io::Result<i32> {
+        let c_path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: the descriptor is open and the path is NUL-terminated.
+        let wd =
+            unsafe { inotify_add_watch(self.inotify.as_raw_fd(), c_path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            let error = io::Error::last_os_error();
+            // ENOSPC means the per-user watch limit, not a full disk.
+            if error.raw_os_error() == Some(ENOSPC) {
+                return Err(io::Error::other(
+                    "inotify watch limit reached (see fs.inotify.max_user_watches)",
+                ));
+            }
+            return Err(error);
+        }
+        // A directory moved within the tree keeps its descriptor.
+        self.watches.insert(wd, dir.to_path_buf());
+        Ok(wd)
+    }
+}
+
+/// Drops the paths that lie below another path of the set.
+fn minimal_paths(paths: BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut minimal: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !minimal.last().is_some_and(|last| path.starts_with(last)) {
+            minimal.push(path);
+        }
+    }
+    minimal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::{digest, tree_hash};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn change(path: PathBuf, old: Option<&[u8]>, new: Option<&[u8]>) -> ChangeEvent {
+        ChangeEvent {
+            path,
+            old: old.map(digest),
+            new: new.map(digest),
+        }
+    }
+
+    /// Waits for the next batch of changes, failing after a few seconds.
+    fn next(watcher: &mut Watcher) -> Vec<ChangeEvent> {
+        let timeout = Some(Duration::from_secs(5));
+        watcher
+            .next_changes(timeout, |path, e| panic!("{:?}: {}", path, e))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_minimal_paths() {
+        let paths = ["a/b", "a", "c/d", "c/e", "cd"].map(PathBuf::from);
+        assert_eq!(
+            minimal_paths(paths.into_iter().collect()),
+            ["a", "c/d", "c/e", "cd"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri does not support inotify")]
+    fn test_watch() {
+        let root = temp_dir("tree");
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("a"), b"one").unwrap();
+        fs::write(root.join("sub/b"), b"two").unwrap();
+
+        let mut watcher = Watcher::new(&root, DigestKind::Tiger, |path, e| {
+            panic!("{:?}: {}", path, e)
+        })
+        .unwrap();
+        watcher.set_debounce(Duration::from_millis(200));
+        assert_eq!(watcher.files().count(), 2);
+
+        // A burst of writes is one change from the first to the last state.
+        for contents in [&b"1"[..], b"12", b"123"] {
+            fs::write(root.join("a"), contents).unwrap();
+        }
+        fs::write(root.join("sub/b"), b"two").unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [change(root.join("a"), Some(b"one"), Some(b"123"))]
+        );
+
+        fs::create_dir(root.join("new")).unwrap();
+        fs::write(root.join("new/c"), b"three").unwrap();
+        fs::remove_file(root.join("sub/b")).unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [
+                change(root.join("new/c"), None, Some(b"three")),
+                change(root.join("sub/b"), Some(b"two"), None),
+            ]
+        );
+
+        fs::rename(root.join("new"), root.join("moved")).unwrap();
+        fs::write(root.join("moved/c"), b"four").unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [
+                change(root.join("moved/c"), None, Some(b"four")),
+                change(root.join("new/c"), Some(b"three"), None),
+            ]
+        );
+        // The moved directory is still watched under its new name.
+        fs::write(root.join("moved/d"), b"five").unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [change(root.join("moved/d"), None, Some(b"five"))]
+        );
+
+        // Moved out of the tree, it isn't watched any more.
+        let outside = temp_dir("outside");
+        fs::rename(root.join("moved"), outside.join("moved")).unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [
+                change(root.join("moved/c"), Some(b"four"), None),
+                change(root.join("moved/d"), Some(b"five"), None),
+            ]
+        );
+        assert_eq!(watcher.watches.len(), 2);
+        assert!(watcher.watches.values().all(|dir| dir.starts_with(&root)));
+
+        assert!(watcher
+            .next_changes(Some(Duration::from_millis(20)), |_, _| {})
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri does not support inotify")]
+    fn test_busy_file() {
+        let root = temp_dir("busy");
+        fs::write(root.join("busy"), b"0").unwrap();
+        fs::write(root.join("other"), b"one").unwrap();
+        let mut watcher = Watcher::new(&root, DigestKind::Tiger, |path, e| {
+            panic!("{:?}: {}", path, e)
+        })
+        .unwrap();
+        watcher.set_debounce(Duration::from_millis(300));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = thread::spawn({
+            let (path, stop) = (root.join("busy"), stop.clone());
+            move || {
+                let mut count = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    count += 1;
+                    fs::write(&path, count.to_string()).unwrap();
+                    thread::sleep(Duration::from_millis(10));
+                }
+                count.to_string()
+            }
+        });
+
+        // A file that is written all the time neither holds up the others
+        // nor keeps a call from returning at its deadline.
+        let start = Instant::now();
+        assert!(watcher
+            .next_changes(Some(Duration::from_millis(100)), |_, _| {})
+            .unwrap()
+            .is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        fs::write(root.join("other"), b"two").unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [change(root.join("other"), Some(b"one"), Some(b"two"))]
+        );
+
+        stop.store(true, Ordering::Relaxed);
+        let last = writer.join().unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [change(root.join("busy"), Some(b"0"), Some(last.as_bytes()))]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri does not support inotify")]
+    fn test_tree_kind() {
+        let root = temp_dir("kind");
+        let mut watcher = Watcher::new(&root, DigestKind::Tree, |_, _| {}).unwrap();
+        watcher.set_debounce(Duration::from_millis(20));
+        fs::write(root.join("f"), [1u8; 3000]).unwrap();
+        assert_eq!(
+            next(&mut watcher),
+            [ChangeEvent {
+                path: root.join("f"),
+                old: None,
+                new: Some(tree_hash(&[1u8; 3000])),
+            }]
+        );
+        fs::remove_dir_all(&root).unwrap();
+        assert!(Watcher::new(&root, DigestKind::Tree, |_, _| {}).is_err());
+    }
+}